@@ -0,0 +1,143 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use midi2mml::reverse::{self, DEFAULT_TICKS_PER_BEAT};
+use midi2mml::OctaveDirection;
+
+struct Args {
+    file_path: String,
+    output_path: Option<String>,
+    ticks_per_beat: u16,
+    octave_direction: OctaveDirection,
+}
+
+fn usage(program: &str) -> String {
+    format!("Usage: {} [OPTIONS] <mml_filepath>", program)
+}
+
+fn help(program: &str) -> String {
+    format!(
+        "{}
+
+Options:
+  -o, --output <path>         Write the MIDI file to <path> (default: input with .mid)
+      --ticks-per-beat <n>    Resolution of the MIDI file (default: {})
+      --octave-up <char>      Character that raises the octave, > or < (default: >)
+  -h, --help                  Print help
+  -V, --version               Print version",
+        usage(program),
+        DEFAULT_TICKS_PER_BEAT
+    )
+}
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(termination::EXIT_FAILURE);
+}
+
+fn parse_args(args: &[String]) -> Result<Option<Args>, String> {
+    let program = args.first().map(|arg| arg.as_str()).unwrap_or("mml2midi");
+
+    let mut file_path: Option<String> = None;
+    let mut output_path: Option<String> = None;
+    let mut ticks_per_beat = DEFAULT_TICKS_PER_BEAT;
+    let mut octave_direction = OctaveDirection::GreaterUp;
+
+    let mut index = 1;
+    while index < args.len() {
+        let arg = args[index].as_str();
+        let mut value = || {
+            index += 1;
+            args.get(index)
+                .map(|value| value.as_str())
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        match arg {
+            "-h" | "--help" => {
+                println!("{}", help(program));
+                return Ok(None);
+            }
+            "-V" | "--version" => {
+                println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+                return Ok(None);
+            }
+            "-o" | "--output" => output_path = Some(value()?.to_string()),
+            "--ticks-per-beat" => {
+                let value = value()?;
+                match value.parse::<u16>() {
+                    Ok(ticks) if ticks > 0 && ticks < 0x8000 => ticks_per_beat = ticks,
+                    _ => return Err(format!("Invalid value for {}: {}", arg, value)),
+                }
+            }
+            "--octave-up" => {
+                octave_direction = match value()? {
+                    ">" => OctaveDirection::GreaterUp,
+                    "<" => OctaveDirection::LessUp,
+                    up => return Err(format!("Invalid value for {}: {}", arg, up)),
+                }
+            }
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("Unknown option: {}", arg))
+            }
+            _ => {
+                if file_path.is_some() {
+                    return Err(usage(program));
+                }
+                file_path = Some(arg.to_string());
+            }
+        }
+        index += 1;
+    }
+
+    match file_path {
+        Some(file_path) => Ok(Some(Args {
+            file_path,
+            output_path,
+            ticks_per_beat,
+            octave_direction,
+        })),
+        None => Err(usage(program)),
+    }
+}
+
+fn read_file(file_path: &str) -> io::Result<String> {
+    let mut text = String::new();
+    if file_path == "-" {
+        io::stdin().read_to_string(&mut text)?;
+    } else {
+        File::open(file_path)?.read_to_string(&mut text)?;
+    }
+    Ok(text)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let args = match parse_args(&args) {
+        Ok(Some(args)) => args,
+        Ok(None) => return,
+        Err(err) => exit_with(&err),
+    };
+
+    let output_path = match (&args.output_path, args.file_path.as_str()) {
+        (Some(path), _) => path.clone(),
+        (None, "-") => exit_with("Reading from stdin needs -o/--output."),
+        (None, path) => Path::new(path)
+            .with_extension("mid")
+            .to_string_lossy()
+            .into_owned(),
+    };
+
+    let text = read_file(&args.file_path).unwrap_or_else(|err| exit_with(&err.to_string()));
+    let data = reverse::mml2midi(&text, args.ticks_per_beat, args.octave_direction)
+        .unwrap_or_else(|err| exit_with(&err.to_string()));
+    let written = if output_path == "-" {
+        io::stdout().lock().write_all(&data)
+    } else {
+        File::create(&output_path).and_then(|mut file| file.write_all(&data))
+    };
+    if let Err(err) = written {
+        exit_with(&err.to_string());
+    }
+}
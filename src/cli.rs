@@ -0,0 +1,266 @@
+use std::fmt;
+
+use midi2mml::dialect::{PmdPolicy, NAMES as DIALECTS};
+use midi2mml::{ConvertOptions, OctaveDirection, OctaveMode, Rounding};
+
+#[derive(PartialEq)]
+pub enum DiagnosticsMode {
+    Stderr,
+    None,
+    Json,
+}
+
+pub struct Args {
+    pub file_path: String,
+    pub output_path: Option<String>,
+    pub dialect: String,
+    pub char_limits: Option<Vec<usize>>,
+    pub pmd_policy: PmdPolicy,
+    pub diagnostics: DiagnosticsMode,
+    pub verbose: bool,
+    pub options: ConvertOptions,
+}
+
+pub enum Command {
+    Convert(Box<Args>),
+    Help,
+    Version,
+}
+
+pub struct ArgsError(String);
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+pub fn usage(program: &str) -> String {
+    format!("Usage: {} [OPTIONS] <midi_filepath>", program)
+}
+
+pub fn help(program: &str) -> String {
+    format!(
+        "{}
+
+Options:
+  -o, --output <path>         Write MML to <path> instead of stdout (\"-\" for stdout)
+  -d, --dialect <name>        MML dialect to emit [{}] (default: rp2040)
+      --char-limits <list>    Character limit of every part for mabinogi (default: 1200,800,500)
+      --pmd-policy <policy>   Assignment of voices to pmd FM and SSG parts,
+                              fm-first, ssg-first or sub-ssg (default: fm-first)
+  -t, --tracks <list>         Convert only these tracks (comma separated, 0-based)
+  -c, --channels <list>       Convert only these MIDI channels (comma separated, 0-based)
+      --min-length <n>        Shortest note length used for quantisation (1-128, default: 128)
+      --no-triplets           Do not use triplet lengths
+      --tuplets <list>        Tuplets written with their own lengths, e.g. 3,5,7 (default: 3)
+      --dots <n>              Maximum number of dots on a length, 0-2 (default: 2)
+      --grid <n>              Quantise to a 1/n grid, e.g. 16, 32 or 24 (default: shortest length)
+      --round <mode>          floor or nearest grid position (default: floor)
+      --swing                 Keep swung off-beats on the triplet position
+      --timecode-tempo <bpm>  Tempo used to map SMPTE timecode files onto beats
+                              (default: first tempo event, or 120)
+      --no-default-length     Write every length explicitly instead of using `l` commands
+      --octave <mode>         absolute, relative or auto (default: auto)
+      --octave-steps <n>      Largest jump written with < and > in auto mode (default: 1)
+      --octave-up <char>      Character that raises the octave, > or < (default: dialect)
+      --title <text>          Song title written to dialect headers (default: first track name)
+      --composer <text>       Composer written to dialect headers
+      --bar-comments          Append the bar number as a comment to every measure line
+      --verify                Read the MML back and report how it differs from the MIDI
+      --diagnostics <mode>    stderr, none or json (default: stderr)
+  -q, --quiet                 Same as --diagnostics none
+  -v, --verbose               Print conversion summary to stderr
+  -h, --help                  Print help
+  -V, --version               Print version",
+        usage(program),
+        DIALECTS.join(", ")
+    )
+}
+
+fn parse_list<T: std::str::FromStr>(option: &str, value: &str) -> Result<Vec<T>, ArgsError> {
+    value
+        .split(',')
+        .map(|item| {
+            item.trim()
+                .parse::<T>()
+                .map_err(|_| ArgsError(format!("Invalid value for {}: {}", option, value)))
+        })
+        .collect()
+}
+
+pub fn parse_args(args: &[String]) -> Result<Command, ArgsError> {
+    let program = args.first().map(|arg| arg.as_str()).unwrap_or("midi2mml");
+
+    let mut file_path: Option<String> = None;
+    let mut output_path: Option<String> = None;
+    let mut dialect = DIALECTS[0].to_string();
+    let mut char_limits: Option<Vec<usize>> = None;
+    let mut pmd_policy: Option<PmdPolicy> = None;
+    let mut diagnostics = DiagnosticsMode::Stderr;
+    let mut verbose = false;
+    let mut options = ConvertOptions::default();
+
+    let mut index = 1;
+    while index < args.len() {
+        let arg = args[index].as_str();
+        let mut value = || {
+            index += 1;
+            args.get(index)
+                .map(|value| value.as_str())
+                .ok_or_else(|| ArgsError(format!("Missing value for {}", arg)))
+        };
+        match arg {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-o" | "--output" => output_path = Some(value()?.to_string()),
+            "-d" | "--dialect" => {
+                let name = value()?;
+                if !DIALECTS.contains(&name) {
+                    return Err(ArgsError(format!("Unknown dialect: {}", name)));
+                }
+                dialect = name.to_string();
+            }
+            "--char-limits" => char_limits = Some(parse_list(arg, value()?)?),
+            "--pmd-policy" => {
+                let value = value()?;
+                match PmdPolicy::from_name(value) {
+                    Some(policy) => pmd_policy = Some(policy),
+                    None => return Err(ArgsError(format!("Invalid value for {}: {}", arg, value))),
+                }
+            }
+            "-t" | "--tracks" => options.tracks = Some(parse_list(arg, value()?)?),
+            "-c" | "--channels" => {
+                let channels: Vec<u8> = parse_list(arg, value()?)?;
+                if channels.iter().any(|channel| *channel > 15) {
+                    return Err(ArgsError(format!("Invalid value for {}", arg)));
+                }
+                options.channels = Some(channels);
+            }
+            "--min-length" => {
+                let value = value()?;
+                match value.parse::<u32>() {
+                    Ok(min_length) if min_length.is_power_of_two() && min_length <= 128 => {
+                        options.min_length = min_length
+                    }
+                    _ => return Err(ArgsError(format!("Invalid value for {}: {}", arg, value))),
+                }
+            }
+            "--no-triplets" => options.tuplets.retain(|notes| *notes != 3),
+            "--tuplets" => {
+                let value = value()?;
+                let tuplets: Vec<u32> = parse_list(arg, value)?;
+                if tuplets
+                    .iter()
+                    .any(|notes| !ConvertOptions::tuplet_supported(*notes))
+                {
+                    return Err(ArgsError(format!("Invalid value for {}: {}", arg, value)));
+                }
+                options.tuplets = tuplets;
+            }
+            "--dots" => {
+                let value = value()?;
+                match value.parse::<u8>() {
+                    Ok(dots) if dots <= 2 => options.max_dots = dots,
+                    _ => return Err(ArgsError(format!("Invalid value for {}: {}", arg, value))),
+                }
+            }
+            "--grid" => {
+                let value = value()?;
+                match value.parse::<u32>() {
+                    Ok(grid) if grid > 0 => options.grid = Some(grid),
+                    _ => return Err(ArgsError(format!("Invalid value for {}: {}", arg, value))),
+                }
+            }
+            "--round" => {
+                options.rounding = match value()? {
+                    "floor" => Rounding::Floor,
+                    "nearest" => Rounding::Nearest,
+                    mode => return Err(ArgsError(format!("Invalid value for {}: {}", arg, mode))),
+                }
+            }
+            "--swing" => options.swing = true,
+            "--title" => options.title = Some(value()?.to_string()),
+            "--composer" => options.composer = Some(value()?.to_string()),
+            "--verify" => options.verify = true,
+            "--bar-comments" => options.bar_comments = true,
+            "--no-default-length" => options.default_length = false,
+            "--octave" => {
+                options.octave_mode = match value()? {
+                    "absolute" => OctaveMode::Absolute,
+                    "relative" => OctaveMode::Relative,
+                    "auto" => OctaveMode::Auto,
+                    mode => return Err(ArgsError(format!("Invalid value for {}: {}", arg, mode))),
+                }
+            }
+            "--octave-steps" => {
+                let value = value()?;
+                match value.parse::<u8>() {
+                    Ok(steps) => options.octave_steps = steps,
+                    _ => return Err(ArgsError(format!("Invalid value for {}: {}", arg, value))),
+                }
+            }
+            "--octave-up" => {
+                options.octave_direction = match value()? {
+                    ">" => Some(OctaveDirection::GreaterUp),
+                    "<" => Some(OctaveDirection::LessUp),
+                    up => return Err(ArgsError(format!("Invalid value for {}: {}", arg, up))),
+                }
+            }
+            "--timecode-tempo" => {
+                let value = value()?;
+                match value.parse::<u32>() {
+                    Ok(tempo) if tempo > 0 => options.timecode_tempo = Some(tempo),
+                    _ => return Err(ArgsError(format!("Invalid value for {}: {}", arg, value))),
+                }
+            }
+            "--diagnostics" => {
+                diagnostics = match value()? {
+                    "stderr" => DiagnosticsMode::Stderr,
+                    "none" => DiagnosticsMode::None,
+                    "json" => DiagnosticsMode::Json,
+                    mode => return Err(ArgsError(format!("Invalid value for {}: {}", arg, mode))),
+                }
+            }
+            "-q" | "--quiet" => diagnostics = DiagnosticsMode::None,
+            "-v" | "--verbose" => verbose = true,
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(ArgsError(format!("Unknown option: {}", arg)))
+            }
+            _ => {
+                if file_path.is_some() {
+                    return Err(ArgsError(usage(program)));
+                }
+                file_path = Some(arg.to_string());
+            }
+        }
+        index += 1;
+    }
+
+    if char_limits.is_some() && dialect != "mabinogi" {
+        return Err(ArgsError(format!(
+            "--char-limits only applies to the mabinogi dialect, not {}",
+            dialect
+        )));
+    }
+    if pmd_policy.is_some() && dialect != "pmd" {
+        return Err(ArgsError(format!(
+            "--pmd-policy only applies to the pmd dialect, not {}",
+            dialect
+        )));
+    }
+
+    match file_path {
+        Some(file_path) => Ok(Command::Convert(Box::new(Args {
+            file_path,
+            output_path,
+            dialect,
+            char_limits,
+            pmd_policy: pmd_policy.unwrap_or(PmdPolicy::FmFirst),
+            diagnostics,
+            verbose,
+            options,
+        }))),
+        None => Err(ArgsError(usage(program))),
+    }
+}
@@ -2,98 +2,229 @@ use midly::MidiMessage::{NoteOff, NoteOn, ProgramChange};
 use midly::Timing::{Metrical, Timecode};
 use midly::TrackEventKind;
 
+use crate::reverse;
+
+pub mod dialect;
+pub use dialect::MmlDialect;
 mod event;
 mod track;
 use track::DataKind;
 use track::Track;
 mod key_data;
 use key_data::KeyData;
+mod length;
+pub use length::Length;
+use length::LengthTable;
+mod measure;
+use measure::MeasureMap;
+mod mml;
+mod tempo;
+pub use mml::Token;
+mod optimize;
+mod quantize;
+use quantize::Quantizer;
+mod verify;
+pub use verify::{NoteMatch, SourceNote, Verification, VerifiedNote};
+mod options;
+pub use options::{ConvertOptions, OctaveDirection, OctaveMode, Rounding};
+mod diagnostic;
+pub use diagnostic::{Diagnostic, DiagnosticKind, Severity};
+mod error;
+pub use error::ConvertError;
+mod result;
+pub use result::{ConversionResult, MmlPart, TrackInfo};
+
+const TIMECODE_TICKS_PER_BEAT: u32 = 480;
+const TIMECODE_DEFAULT_TEMPO: u32 = 120;
 
 pub struct Converter {
     data: Vec<u8>,
     ticks_per_beat: u32,
+    options: ConvertOptions,
+    dialect: Box<dyn MmlDialect>,
 }
 
 #[derive(Clone, Copy, PartialEq)]
 struct TempoEvent {
     tick: u32,
-    tempo: u32,
+    microseconds_per_beat: u32,
+    // Value written to the MML, see `tempo::compensate`.
+    tempo: f64,
     enable: bool,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+struct TimeSignatureEvent {
+    tick: u32,
+    numerator: u8,
+    denominator: u8,
+}
+
+impl TimeSignatureEvent {
+    fn new(tick: u32, numerator: u8, denominator_pow: u8) -> Self {
+        TimeSignatureEvent {
+            tick,
+            numerator,
+            denominator: 1u8.checked_shl(denominator_pow as u32).unwrap_or(4),
+        }
+    }
+}
+
 impl TempoEvent {
-    fn new(tick: u32, tempo: u32) -> Self {
-        TempoEvent {
+    fn new(tick: u32, microseconds_per_beat: u32) -> Self {
+        let mut event = TempoEvent {
             tick,
-            tempo,
+            microseconds_per_beat,
+            tempo: 0.0,
             enable: true,
-        }
+        };
+        event.tempo = event.beats_per_minute();
+        event
+    }
+
+    fn beats_per_minute(&self) -> f64 {
+        60_000_000.0 / f64::from(self.microseconds_per_beat.max(1))
     }
 }
 
 impl Converter {
     pub fn new(data: Vec<u8>) -> Self {
+        Self::with_options(data, ConvertOptions::default())
+    }
+
+    pub fn with_options(data: Vec<u8>, options: ConvertOptions) -> Self {
+        Self::with_dialect(data, options, Box::new(dialect::Rp2040))
+    }
+
+    pub fn with_dialect(
+        data: Vec<u8>,
+        options: ConvertOptions,
+        dialect: Box<dyn MmlDialect>,
+    ) -> Self {
         Converter {
             data,
             ticks_per_beat: 0,
+            options,
+            dialect,
         }
     }
 
+    // Options limited to what the dialect can express.
+    fn dialect_options(&self) -> ConvertOptions {
+        let mut options = self.options.clone();
+        options.max_dots = options.max_dots.min(self.dialect.max_dots());
+        options.min_length = options.min_length.min(self.dialect.min_length());
+        options.default_length &= self.dialect.supports_default_length();
+        if !self.dialect.supports_relative_octave() {
+            options.octave_mode = OctaveMode::Absolute;
+        }
+        options
+    }
+
     fn delete_duplicate_tempo(events: &mut Vec<TempoEvent>) {
         let mut events_delete: Vec<TempoEvent> = Vec::new();
-        if events.len() > 0 {
+        if !events.is_empty() {
             let mut pre_tempo = events[0].tempo;
-            for index in 1..events.len() {
-                if events[index].tempo == pre_tempo {
-                    events_delete.push(events[index]);
+            for event in events.iter().skip(1) {
+                if event.tempo == pre_tempo {
+                    events_delete.push(*event);
                 } else {
-                    pre_tempo = events[index].tempo;
+                    pre_tempo = event.tempo;
                 }
             }
         }
         events.retain(|event| !events_delete.contains(event));
     }
 
-    fn get_tempo(microseconds_per_beat: u32) -> u32 {
-        let microseconds_per_beat_f = f64::from(microseconds_per_beat);
-        let seconds_per_beat = microseconds_per_beat_f / 1_000_000.0;
-        let beats_per_minute = 60.0 / seconds_per_beat;
-        beats_per_minute as u32
-    }
-
-    fn map_range(value: u8, from_low: u8, from_high: u8, to_low: u8, to_high: u8) -> u8 {
-        let normalized_value = (value - from_low) as f64 / (from_high - from_low) as f64;
-        let mapped_value =
-            (normalized_value * (to_high - to_low) as f64 + to_low as f64).round() as u8;
-
-        mapped_value.clamp(to_low, to_high)
+    fn detect_tempo(smf: &midly::Smf) -> Option<u32> {
+        let mut first: Option<(u32, u32)> = None;
+        for track_events in smf.tracks.iter() {
+            let mut ticks: u32 = 0;
+            for track_event in track_events.iter() {
+                ticks += track_event.delta.as_int();
+                if let TrackEventKind::Meta(midly::MetaMessage::Tempo(tempo)) = track_event.kind {
+                    if first.is_none_or(|(tick, _)| ticks < tick) {
+                        first = Some((ticks, tempo.as_int()));
+                    }
+                    break;
+                }
+            }
+        }
+        first.map(|(_, tempo)| tempo)
     }
 
-    fn get_vol(vel: u8) -> u8 {
-        Self::map_range(vel, 0, 127, 0, 100)
-    }
+    pub fn convert(&mut self) -> Result<ConversionResult, ConvertError> {
+        let smf = midly::Smf::parse(&self.data)?;
+        let options = self.dialect_options();
+        if let Some(notes) = options
+            .tuplets
+            .iter()
+            .find(|notes| !ConvertOptions::tuplet_supported(**notes))
+        {
+            return Err(ConvertError::TupletUnsupported { notes: *notes });
+        }
 
-    pub fn convert(&mut self) -> Result<(), String> {
-        let smf = match midly::Smf::parse(&mut self.data) {
-            Ok(result) => result,
-            Err(err) => return Err(err.to_string()),
+        let mut result = ConversionResult {
+            dialect: self.dialect.name().to_string(),
+            title: self.options.title.clone(),
+            composer: self.options.composer.clone(),
+            ..Default::default()
         };
+        let mut tempo_events: Vec<TempoEvent> = Vec::new();
+        let mut time_signature_events: Vec<TimeSignatureEvent> = Vec::new();
 
+        // Timecode deltas are sub-frames of real time. They are mapped onto a metrical
+        // grid at a single tempo, so the MML plays back at the original timing.
+        let mut timecode_scale: Option<f64> = None;
         match smf.header.timing {
             Metrical(ticks_per_beat) => self.ticks_per_beat = ticks_per_beat.as_int() as u32,
-            Timecode(ticks_per_frame, frames_per_second) => return Err(format!("Ticks per frame: {}\nFrames per second: {}\nMIDI files with timecode are not supported.",ticks_per_frame.as_f32(),frames_per_second)),
+            Timecode(frames_per_second, ticks_per_frame) => {
+                let ticks_per_second = frames_per_second.as_f32() as f64 * ticks_per_frame as f64;
+                if ticks_per_second <= 0.0 {
+                    return Err(ConvertError::TimecodeUnsupported {
+                        frames_per_second: frames_per_second.as_f32(),
+                        ticks_per_frame,
+                    });
+                }
+                let tempo = TempoEvent::new(
+                    0,
+                    match self.options.timecode_tempo {
+                        Some(tempo) => 60_000_000 / tempo.max(1),
+                        None => {
+                            Self::detect_tempo(&smf).unwrap_or(60_000_000 / TIMECODE_DEFAULT_TEMPO)
+                        }
+                    },
+                );
+                self.ticks_per_beat = TIMECODE_TICKS_PER_BEAT;
+                timecode_scale = Some(
+                    TIMECODE_TICKS_PER_BEAT as f64 * tempo.beats_per_minute()
+                        / (ticks_per_second * 60.0),
+                );
+                tempo_events.push(tempo);
+                result.diagnostics.push(
+                    Diagnostic::song(Severity::Info, DiagnosticKind::TimecodeConverted, 0)
+                        .with_message(format!(
+                            "Timecode {} fps x {} ticks per frame converted at t{} ({} ticks per beat).",
+                            frames_per_second.as_f32(),
+                            ticks_per_frame,
+                            (tempo.beats_per_minute() * 100.0).round() / 100.0,
+                            TIMECODE_TICKS_PER_BEAT
+                        )),
+                );
+            }
         }
-        println!("ticks_per_beat {}", self.ticks_per_beat);
-        println!("track num: {}", smf.tracks.len());
+        result.ticks_per_beat = self.ticks_per_beat;
 
         let mut tracks: Vec<Track> = Vec::new();
-        let mut tempo_events: Vec<TempoEvent> = Vec::new();
         for (track_num, track_events) in smf.tracks.iter().enumerate() {
             tracks.push(Track::new(track_num));
-            println!("track {} has {} events", track_num, track_events.len());
-            let mut ticks: u32 = 0;
+            let mut raw_ticks: u32 = 0;
             for track_event in track_events.iter() {
-                ticks += track_event.delta.as_int();
+                raw_ticks += track_event.delta.as_int();
+                let ticks = match timecode_scale {
+                    Some(scale) => (raw_ticks as f64 * scale).round() as u32,
+                    None => raw_ticks,
+                };
                 match track_event.kind {
                     TrackEventKind::Midi { channel, message } => {
                         match message {
@@ -116,10 +247,7 @@ impl Converter {
                                     tracks[track_num].push_event(
                                         channel.as_int(),
                                         ticks,
-                                        DataKind::NoteOn(KeyData::new(
-                                            key.as_int(),
-                                            Self::get_vol(vel.as_int()),
-                                        )),
+                                        DataKind::NoteOn(KeyData::new(key.as_int(), vel.as_int())),
                                     );
                                 }
                                 //println!("Ticks:{} NoteOn Key:{} Vel:{}", ticks, key, vel);
@@ -165,50 +293,236 @@ impl Converter {
                         println!("Escape Event - Data: {:?}", data);
                     }
                     */
-                    TrackEventKind::Meta(data) => {
-                        if let midly::MetaMessage::Tempo(tempo) = data {
-                            tempo_events
-                                .push(TempoEvent::new(ticks, Self::get_tempo(tempo.as_int())));
+                    TrackEventKind::Meta(midly::MetaMessage::Tempo(tempo)) => {
+                        if timecode_scale.is_none() {
+                            tempo_events.push(TempoEvent::new(ticks, tempo.as_int()));
+                        } else if tempo.as_int() != tempo_events[0].microseconds_per_beat {
+                            result.diagnostics.push(Diagnostic::song(
+                                Severity::Warning,
+                                DiagnosticKind::TimecodeTempoIgnored,
+                                ticks,
+                            ));
                         }
                         //println!("Meta Event - Data: {:?}", data);
                     }
+                    TrackEventKind::Meta(midly::MetaMessage::TimeSignature(
+                        numerator,
+                        denominator_pow,
+                        _,
+                        _,
+                    )) if numerator > 0 => {
+                        time_signature_events.push(TimeSignatureEvent::new(
+                            ticks,
+                            numerator,
+                            denominator_pow,
+                        ));
+                    }
+                    TrackEventKind::Meta(midly::MetaMessage::TrackName(name))
+                        if track_num == 0 && result.title.is_none() =>
+                    {
+                        let name = String::from_utf8_lossy(name).trim().to_string();
+                        if !name.is_empty() {
+                            result.title = Some(name);
+                        }
+                    }
                     _ => (),
                 }
             }
+            result.tracks.push(TrackInfo {
+                index: track_num,
+                event_count: track_events.len(),
+                channels: tracks[track_num].channels.clone(),
+            });
+        }
+
+        for track in tracks.iter_mut() {
+            if !self.options.track_selected(track.track_index) {
+                track.events.clear();
+            }
+            track
+                .events
+                .retain(|event| self.options.channel_selected(event.channel));
         }
 
+        if options.verify && self.dialect.name() != dialect::Rp2040.name() {
+            return Err(ConvertError::VerifyUnsupported {
+                dialect: self.dialect.name().to_string(),
+            });
+        }
+        let mut source_notes: Vec<SourceNote> = Vec::new();
+        for track in tracks.iter() {
+            source_notes.append(&mut SourceNote::collect(track.track_index, &track.events));
+        }
+
+        time_signature_events.sort_by_key(|e| e.tick);
+        let measure_map = MeasureMap::new(self.ticks_per_beat, &time_signature_events);
+
+        let end_tick = source_notes
+            .iter()
+            .map(|note| note.tick + note.ticks)
+            .max()
+            .unwrap_or(0);
+        result.diagnostics.append(&mut tempo::compensate(
+            &mut tempo_events,
+            self.ticks_per_beat,
+            self.dialect.tempo_decimals(),
+            end_tick,
+            &measure_map,
+            &source_notes,
+        ));
         Self::delete_duplicate_tempo(&mut tempo_events);
 
-        for index in 0..tracks.len() {
-            if let Err(msg) = tracks[index].parse1(&mut tempo_events) {
-                return Err(msg);
+        let length_table = LengthTable::new(self.ticks_per_beat, &options, self.dialect.as_ref());
+        let mut quantizer = Quantizer::new(&length_table, self.ticks_per_beat, &options)?;
+        if options.swing && quantizer.detect_swing(&source_notes) {
+            result.diagnostics.push(Diagnostic::song(
+                Severity::Info,
+                DiagnosticKind::SwingDetected,
+                0,
+            ));
+        }
+
+        for track in tracks.iter_mut() {
+            track.parse1(&mut tempo_events, self.dialect.supports_tempo_in_tie())?;
+        }
+
+        for track in tracks.iter_mut() {
+            if !track.events.is_empty() {
+                track.parse2(&quantizer, &mut tempo_events)?;
             }
         }
 
-        for index in 0..tracks.len() {
-            if tracks[index].events.len() > 0 {
-                if let Err(msg) = tracks[index].parse2(self.ticks_per_beat, &mut tempo_events) {
-                    return Err(msg);
-                }
+        for track in tracks.iter_mut() {
+            if !track.events.is_empty() {
+                track.parse3(&mut tempo_events)?;
             }
         }
 
-        for index in 0..tracks.len() {
-            if tracks[index].events.len() > 0 {
-                if let Err(msg) = tracks[index].parse3(&mut tempo_events) {
-                    return Err(msg);
-                }
+        for track in tracks.iter_mut() {
+            if !track.events.is_empty() {
+                result.parts.append(&mut track.convert(
+                    &length_table,
+                    &measure_map,
+                    &options,
+                    self.dialect.as_ref(),
+                ));
             }
+            result.diagnostics.append(&mut track.diagnostics);
         }
 
-        println!("Analysis complete!\n");
+        self.dialect
+            .arrange(&mut result.parts, &mut result.diagnostics)?;
 
-        for index in 0..tracks.len() {
-            if tracks[index].events.len() > 0 {
-                tracks[index].convert(self.ticks_per_beat);
-            }
+        let octave_direction = options
+            .octave_direction
+            .unwrap_or(self.dialect.octave_direction());
+        for part in result.parts.iter_mut() {
+            let mut mml: Vec<u8> = Vec::new();
+            mml::render(
+                &part.tokens,
+                options.bar_comments,
+                octave_direction,
+                self.dialect
+                    .part_dialect(part)
+                    .unwrap_or(self.dialect.as_ref()),
+                &mut mml,
+            )?;
+            part.mml = String::from_utf8_lossy(&mml).into_owned();
+        }
+
+        let mut document: Vec<u8> = Vec::new();
+        self.dialect.write_document(&mut document, &result)?;
+        result.document = String::from_utf8_lossy(&document).into_owned();
+
+        // The MML is read back the way a player would, so everything lost on the way
+        // shows up as a difference to the source notes.
+        if options.verify {
+            let song = reverse::parse(
+                &result.document,
+                self.ticks_per_beat.min(u16::MAX as u32) as u16,
+                octave_direction,
+            )?;
+            result.verification = Some(Verification::new(self.ticks_per_beat, source_notes, &song));
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn smf(mml: &str) -> Vec<u8> {
+        let song = reverse::parse(mml, 480, OctaveDirection::GreaterUp).unwrap();
+        let mut data: Vec<u8> = Vec::new();
+        reverse::write_smf(&song, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn unsupported_tuplets() {
+        for notes in [0, 1, 2, 4, 32] {
+            let options = ConvertOptions {
+                tuplets: vec![3, notes],
+                ..ConvertOptions::default()
+            };
+            assert!(
+                matches!(
+                    Converter::with_options(smf("c4"), options).convert(),
+                    Err(ConvertError::TupletUnsupported { notes: error_notes }) if error_notes == notes
+                ),
+                "tuplet {}",
+                notes
+            );
         }
+        let options = ConvertOptions {
+            tuplets: vec![3, 5, 31],
+            ..ConvertOptions::default()
+        };
+        assert!(Converter::with_options(smf("c4"), options)
+            .convert()
+            .is_ok());
+    }
 
-        Ok(())
+    #[test]
+    fn timecode_tempo_zero() {
+        let note = |delta: u32, message| midly::TrackEvent {
+            delta: delta.into(),
+            kind: TrackEventKind::Midi {
+                channel: 0.into(),
+                message,
+            },
+        };
+        let smf = midly::Smf {
+            header: midly::Header::new(midly::Format::SingleTrack, Timecode(midly::Fps::Fps25, 40)),
+            tracks: vec![vec![
+                note(
+                    0,
+                    NoteOn {
+                        key: 60.into(),
+                        vel: 100.into(),
+                    },
+                ),
+                note(
+                    500,
+                    NoteOff {
+                        key: 60.into(),
+                        vel: 0.into(),
+                    },
+                ),
+                midly::TrackEvent {
+                    delta: 0.into(),
+                    kind: TrackEventKind::Meta(midly::MetaMessage::EndOfTrack),
+                },
+            ]],
+        };
+        let mut data: Vec<u8> = Vec::new();
+        smf.write_std(&mut data).unwrap();
+        let options = ConvertOptions {
+            timecode_tempo: Some(0),
+            ..ConvertOptions::default()
+        };
+        assert!(Converter::with_options(data, options).convert().is_ok());
     }
 }
@@ -0,0 +1,198 @@
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+    Info,
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DiagnosticKind {
+    ContinuousNoteOn,
+    NoteOffWithoutNoteOn,
+    TempoChangeDuringNote,
+    TimbreChangeDuringNote,
+    NoteOnCorrected,
+    NoteOffCorrected,
+    TempoChangeUnaligned,
+    TimecodeConverted,
+    TimecodeTempoIgnored,
+    TempoAlternated,
+    NoteExtended,
+    SwingDetected,
+    KeyOutOfRange,
+    VoiceDropped,
+    PartTruncated,
+    MeasuresFit,
+}
+
+impl DiagnosticKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiagnosticKind::ContinuousNoteOn => "continuous_note_on",
+            DiagnosticKind::NoteOffWithoutNoteOn => "note_off_without_note_on",
+            DiagnosticKind::TempoChangeDuringNote => "tempo_change_during_note",
+            DiagnosticKind::TimbreChangeDuringNote => "timbre_change_during_note",
+            DiagnosticKind::NoteOnCorrected => "note_on_corrected",
+            DiagnosticKind::NoteOffCorrected => "note_off_corrected",
+            DiagnosticKind::TempoChangeUnaligned => "tempo_change_unaligned",
+            DiagnosticKind::TimecodeConverted => "timecode_converted",
+            DiagnosticKind::TimecodeTempoIgnored => "timecode_tempo_ignored",
+            DiagnosticKind::TempoAlternated => "tempo_alternated",
+            DiagnosticKind::NoteExtended => "note_extended",
+            DiagnosticKind::SwingDetected => "swing_detected",
+            DiagnosticKind::KeyOutOfRange => "key_out_of_range",
+            DiagnosticKind::VoiceDropped => "voice_dropped",
+            DiagnosticKind::PartTruncated => "part_truncated",
+            DiagnosticKind::MeasuresFit => "measures_fit",
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            DiagnosticKind::ContinuousNoteOn => "Continuous NoteOn is not supported.",
+            DiagnosticKind::NoteOffWithoutNoteOn => "No Key to NoteOff was found.",
+            DiagnosticKind::TempoChangeDuringNote => {
+                "Tempo changes in the middle of a sound are not supported."
+            }
+            DiagnosticKind::TimbreChangeDuringNote => {
+                "Timbre changes in the middle of a sound are deferred."
+            }
+            DiagnosticKind::NoteOnCorrected => "Corrects NoteOn timing.",
+            DiagnosticKind::NoteOffCorrected => "Corrects NoteOff timing.",
+            DiagnosticKind::TempoChangeUnaligned => "Unsupport Change Tempo timing.",
+            DiagnosticKind::TimecodeConverted => "Timecode converted to a metrical grid.",
+            DiagnosticKind::TimecodeTempoIgnored => {
+                "Tempo changes in a timecode file do not affect timing and are ignored."
+            }
+            DiagnosticKind::TempoAlternated => {
+                "Tempo alternates between the nearest values the dialect can write."
+            }
+            DiagnosticKind::NoteExtended => "Note shorter than the grid extended to one step.",
+            DiagnosticKind::SwingDetected => "Swing detected, off-beats are kept on triplets.",
+            DiagnosticKind::KeyOutOfRange => {
+                "Key outside the range of the dialect, played in the nearest octave."
+            }
+            DiagnosticKind::VoiceDropped => "No voice of the dialect is left for this part.",
+            DiagnosticKind::PartTruncated => "Part truncated to fit the character limit.",
+            DiagnosticKind::MeasuresFit => "Measures that fit the character limits.",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    pub track: Option<usize>,
+    pub tick: u32,
+    pub channel: Option<u8>,
+    pub sub_channel: Option<u8>,
+    pub key: Option<u8>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn warning(
+        kind: DiagnosticKind,
+        track: usize,
+        tick: u32,
+        channel: u8,
+        sub_channel: Option<u8>,
+        key: Option<u8>,
+    ) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            kind,
+            track: Some(track),
+            tick,
+            channel: Some(channel),
+            sub_channel,
+            key,
+            message: kind.message().to_string(),
+        }
+    }
+
+    pub fn song(severity: Severity, kind: DiagnosticKind, tick: u32) -> Self {
+        Diagnostic {
+            severity,
+            kind,
+            track: None,
+            tick,
+            channel: None,
+            sub_channel: None,
+            key: None,
+            message: kind.message().to_string(),
+        }
+    }
+
+    pub fn with_message(mut self, message: String) -> Self {
+        self.message = message;
+        self
+    }
+
+    pub fn to_json(&self) -> String {
+        fn option<T: ToString>(value: Option<T>) -> String {
+            match value {
+                Some(value) => value.to_string(),
+                None => "null".to_string(),
+            }
+        }
+        fn escape(value: &str) -> String {
+            let mut escaped = String::new();
+            for c in value.chars() {
+                match c {
+                    '"' => escaped.push_str("\\\""),
+                    '\\' => escaped.push_str("\\\\"),
+                    '\n' => escaped.push_str("\\n"),
+                    c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+                    c => escaped.push(c),
+                }
+            }
+            escaped
+        }
+        format!(
+            "{{\"severity\":\"{}\",\"kind\":\"{}\",\"track\":{},\"tick\":{},\"channel\":{},\"sub_channel\":{},\"key\":{},\"message\":\"{}\"}}",
+            self.severity.as_str(),
+            self.kind.as_str(),
+            option(self.track),
+            self.tick,
+            option(self.channel),
+            option(self.sub_channel),
+            option(self.key),
+            escape(&self.message)
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Info => write!(f, "Info:")?,
+            Severity::Warning => write!(f, "Warning!")?,
+        }
+        if let Some(track) = self.track {
+            write!(f, " Track:{}", track)?;
+        }
+        write!(f, " Tick:{}", self.tick)?;
+        if let Some(channel) = self.channel {
+            write!(f, " Channel:{}", channel)?;
+        }
+        if let Some(sub_channel) = self.sub_channel {
+            write!(f, " Sub:{}", sub_channel)?;
+        }
+        if let Some(key) = self.key {
+            write!(f, " Key:{}", key)?;
+        }
+        write!(f, " {}", self.message)
+    }
+}
@@ -0,0 +1,310 @@
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use super::length::Length;
+use super::{ConversionResult, ConvertError, Diagnostic, MmlPart, OctaveDirection};
+
+mod gameboy;
+pub use gameboy::GameBoy;
+mod mabinogi;
+pub use mabinogi::Mabinogi;
+mod msx;
+pub use msx::Msx;
+mod mxdrv;
+pub use mxdrv::Mxdrv;
+mod pmd;
+pub use pmd::{Pmd, PmdPolicy};
+mod ppmck;
+pub use ppmck::Ppmck;
+mod rp2040;
+pub use rp2040::Rp2040;
+mod sakura;
+pub use sakura::Sakura;
+
+pub const NAMES: [&str; 8] = [
+    "rp2040", "ppmck", "msx", "mabinogi", "pmd", "sakura", "gameboy", "mxdrv",
+];
+
+pub fn by_name(name: &str) -> Option<Box<dyn MmlDialect>> {
+    match name {
+        "rp2040" => Some(Box::new(Rp2040)),
+        "ppmck" => Some(Box::new(Ppmck)),
+        "msx" => Some(Box::new(Msx)),
+        "mabinogi" => Some(Box::new(Mabinogi::default())),
+        "pmd" => Some(Box::new(Pmd::new(PmdPolicy::FmFirst))),
+        "sakura" => Some(Box::new(Sakura)),
+        "gameboy" => Some(Box::new(GameBoy::new())),
+        "mxdrv" => Some(Box::new(Mxdrv)),
+        _ => None,
+    }
+}
+
+pub const NOTE_NAMES: [&str; 12] = [
+    "c", "c+", "d", "d+", "e", "f", "f+", "g", "g+", "a", "a+", "b",
+];
+
+pub const DRUM_CHANNEL: u8 = 9;
+pub const OCTAVE_MIN: u8 = 1;
+pub const OCTAVE_MAX: u8 = 8;
+// Keys written as notes in the octaves `OCTAVE_MIN` to `OCTAVE_MAX` of `octave_a440`.
+pub const OCTAVE_KEYS: RangeInclusive<u8> = (OCTAVE_MIN + 1) * 12..=(OCTAVE_MAX + 2) * 12 - 1;
+
+// o4a is 440Hz, keys beyond o1-o8 are played in the nearest octave.
+pub fn octave_a440(key: u8) -> u8 {
+    (key / 12).saturating_sub(1).clamp(OCTAVE_MIN, OCTAVE_MAX)
+}
+
+pub fn octave_step(up: bool, direction: OctaveDirection) -> &'static str {
+    match (up, direction) {
+        (true, OctaveDirection::GreaterUp) | (false, OctaveDirection::LessUp) => ">",
+        _ => "<",
+    }
+}
+
+// Every part under a comment naming its source, with the label in front of each line.
+pub fn write_part_header(
+    dialect: &dyn MmlDialect,
+    out: &mut dyn Write,
+    part: &MmlPart,
+) -> io::Result<()> {
+    writeln!(out)?;
+    dialect.write_comment(
+        out,
+        &format!(
+            " Track:{} Channel:{} Sub:{}",
+            part.track, part.channel, part.sub_channel
+        ),
+    )?;
+    writeln!(out)
+}
+
+pub fn write_labelled_lines(
+    out: &mut dyn Write,
+    label: &str,
+    separator: &str,
+    mml: &str,
+) -> io::Result<()> {
+    for line in mml.lines().filter(|line| !line.is_empty()) {
+        writeln!(out, "{}{}{}", label, separator, line)?;
+    }
+    Ok(())
+}
+
+pub fn write_labelled_parts(
+    dialect: &dyn MmlDialect,
+    out: &mut dyn Write,
+    parts: &[MmlPart],
+    separator: &str,
+) -> io::Result<()> {
+    for part in parts.iter() {
+        write_part_header(dialect, out, part)?;
+        write_labelled_lines(
+            out,
+            part.label.as_deref().unwrap_or_default(),
+            separator,
+            &part.mml,
+        )?;
+    }
+    Ok(())
+}
+
+pub fn map_range(value: u8, from_low: u8, from_high: u8, to_low: u8, to_high: u8) -> u8 {
+    let normalized_value = (value - from_low) as f64 / (from_high - from_low) as f64;
+    let mapped_value = (normalized_value * (to_high - to_low) as f64 + to_low as f64).round() as u8;
+
+    mapped_value.clamp(to_low, to_high)
+}
+
+// The default method bodies write the syntax of the RP2040 MML Player. A dialect
+// only overrides what differs; the emitter calls one method for every token.
+pub trait MmlDialect {
+    fn name(&self) -> &str;
+
+    fn max_dots(&self) -> u8 {
+        2
+    }
+
+    fn min_length(&self) -> u32 {
+        128
+    }
+
+    // Whether a note length other than a power of two or a triplet can be written,
+    // such as `c20` for quintuplets.
+    fn supports_length(&self, _denominator: u32) -> bool {
+        true
+    }
+
+    fn supports_default_length(&self) -> bool {
+        true
+    }
+
+    fn supports_relative_octave(&self) -> bool {
+        true
+    }
+
+    // Whether a tempo change can sit between two tied segments of a note (`c4&t90c4`).
+    fn supports_tempo_in_tie(&self) -> bool {
+        true
+    }
+
+    // Decimal places of a tempo. Other tempos alternate between the nearest values.
+    fn tempo_decimals(&self) -> u8 {
+        0
+    }
+
+    fn octave_direction(&self) -> OctaveDirection {
+        OctaveDirection::GreaterUp
+    }
+
+    // Volume and octave the player starts every part with. None writes the command
+    // before the first note.
+    fn initial_volume(&self) -> Option<u8> {
+        Some(75)
+    }
+
+    fn initial_octave(&self) -> Option<u8> {
+        Some(4)
+    }
+
+    fn volume(&self, velocity: u8) -> u8 {
+        map_range(velocity, 0, 127, 0, 100)
+    }
+
+    fn octave(&self, key: u8) -> u8 {
+        key / 12
+    }
+
+    // Keys the dialect plays at their pitch, others get a diagnostic.
+    fn key_range(&self) -> RangeInclusive<u8> {
+        0..=127
+    }
+
+    fn write_length(
+        &self,
+        out: &mut dyn Write,
+        length: &Length,
+        default: Option<Length>,
+    ) -> io::Result<()> {
+        if default != Some(*length) {
+            write!(out, "{}", length)?;
+        }
+        Ok(())
+    }
+
+    fn write_note_name(&self, out: &mut dyn Write, key: u8) -> io::Result<()> {
+        write!(out, "{}", NOTE_NAMES[(key % 12) as usize])
+    }
+
+    fn write_note(
+        &self,
+        out: &mut dyn Write,
+        key: u8,
+        lengths: &[Length],
+        default: Option<Length>,
+    ) -> io::Result<()> {
+        for (index, length) in lengths.iter().enumerate() {
+            if index > 0 {
+                self.write_tie(out)?;
+            }
+            self.write_note_name(out, key)?;
+            self.write_length(out, length, default)?;
+        }
+        Ok(())
+    }
+
+    fn write_tie(&self, out: &mut dyn Write) -> io::Result<()> {
+        write!(out, "&")
+    }
+
+    // The rest of a note after a tie, which may follow a tempo change or a bar line.
+    fn write_tied_note(
+        &self,
+        out: &mut dyn Write,
+        key: u8,
+        lengths: &[Length],
+        default: Option<Length>,
+    ) -> io::Result<()> {
+        self.write_note(out, key, lengths, default)
+    }
+
+    fn write_rest(
+        &self,
+        out: &mut dyn Write,
+        length: &Length,
+        default: Option<Length>,
+    ) -> io::Result<()> {
+        write!(out, "r")?;
+        self.write_length(out, length, default)
+    }
+
+    fn write_tempo(&self, out: &mut dyn Write, tempo: f64) -> io::Result<()> {
+        write!(out, "t{}", tempo)
+    }
+
+    fn write_volume(&self, out: &mut dyn Write, volume: u8) -> io::Result<()> {
+        write!(out, "v{}", volume)
+    }
+
+    fn write_octave(&self, out: &mut dyn Write, octave: u8) -> io::Result<()> {
+        write!(out, "o{}", octave)
+    }
+
+    fn write_octave_step(
+        &self,
+        out: &mut dyn Write,
+        up: bool,
+        direction: OctaveDirection,
+    ) -> io::Result<()> {
+        write!(out, "{}", octave_step(up, direction))
+    }
+
+    fn write_timbre(&self, out: &mut dyn Write, timbre: u8) -> io::Result<()> {
+        write!(out, "@{}", timbre)
+    }
+
+    fn write_default_length(&self, out: &mut dyn Write, length: &Length) -> io::Result<()> {
+        write!(out, "l{}", length)
+    }
+
+    fn write_comment(&self, out: &mut dyn Write, comment: &str) -> io::Result<()> {
+        write!(out, ";{}", comment)
+    }
+
+    fn write_bar_line(&self, out: &mut dyn Write, bar: u32, comment: bool) -> io::Result<()> {
+        if comment {
+            write!(out, " ")?;
+            self.write_comment(out, &bar.to_string())?;
+        }
+        writeln!(out)
+    }
+
+    // Called with all parts before they are rendered. Dialects with a fixed set of
+    // voices assign, reorder or drop parts here.
+    fn arrange(
+        &self,
+        _parts: &mut Vec<MmlPart>,
+        _diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<(), ConvertError> {
+        Ok(())
+    }
+
+    // Dialect that renders `part` when its sound source uses a different syntax.
+    fn part_dialect(&self, _part: &MmlPart) -> Option<&dyn MmlDialect> {
+        None
+    }
+
+    fn write_document(&self, out: &mut dyn Write, result: &ConversionResult) -> io::Result<()> {
+        for part in result.parts.iter() {
+            self.write_comment(
+                out,
+                &format!(
+                    "########## Track:{} Channel:{} Sub:{} ##########",
+                    part.track, part.channel, part.sub_channel
+                ),
+            )?;
+            writeln!(out)?;
+            writeln!(out, "{}", part.mml)?;
+        }
+        Ok(())
+    }
+}
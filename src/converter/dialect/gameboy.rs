@@ -0,0 +1,251 @@
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use super::{
+    map_range, octave_a440, octave_step, write_labelled_parts, MmlDialect, DRUM_CHANNEL,
+    NOTE_NAMES, OCTAVE_KEYS,
+};
+use crate::converter::mml::Token;
+use crate::converter::{
+    ConversionResult, ConvertError, Diagnostic, DiagnosticKind, MmlPart, OctaveDirection,
+};
+
+// Pulse 1 and 2 take the two highest voices, the wave channel the lowest.
+const MELODIC_CHANNELS: [&str; 3] = ["A", "B", "C"];
+const NOISE_CHANNEL: &str = "D";
+const PULSE_DUTIES: u8 = 4;
+// Envelope sweep written after the volume, negative values fade out.
+const DECAY_ENVELOPE: i8 = -3;
+const NOISE_ENVELOPE: i8 = -2;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Pulse,
+    Wave,
+    Noise,
+}
+
+struct GameBoyChannel {
+    source: Source,
+    envelope: i8,
+}
+
+impl MmlDialect for GameBoyChannel {
+    fn name(&self) -> &str {
+        "gameboy"
+    }
+
+    // The noise channel plays a `c` of a fixed octave per drum.
+    fn write_note_name(&self, out: &mut dyn Write, key: u8) -> io::Result<()> {
+        if self.source != Source::Noise {
+            return write!(out, "{}", NOTE_NAMES[(key % 12) as usize]);
+        }
+        let octave = match key {
+            35 | 36 => 2,
+            41 | 43 | 45 | 47 | 48 | 50 => 3,
+            49 | 51 | 52 | 55 | 57 | 59 => 5,
+            42 | 44 | 46 => 6,
+            _ => 4,
+        };
+        write!(out, "o{}c", octave)
+    }
+
+    // The wave channel only has mute, 25%, 50% and 100%.
+    fn write_volume(&self, out: &mut dyn Write, volume: u8) -> io::Result<()> {
+        let volume = match (self.source, volume) {
+            (Source::Wave, 0) => 0,
+            (Source::Wave, 1..=5) => 4,
+            (Source::Wave, 6..=11) => 8,
+            (Source::Wave, _) => 15,
+            _ => volume,
+        };
+        write!(out, "v{}", volume)?;
+        if self.envelope != 0 {
+            write!(out, ",{}", self.envelope)?;
+        }
+        Ok(())
+    }
+
+    fn write_octave(&self, out: &mut dyn Write, octave: u8) -> io::Result<()> {
+        match self.source {
+            Source::Noise => Ok(()),
+            _ => write!(out, "o{}", octave),
+        }
+    }
+
+    fn write_octave_step(
+        &self,
+        out: &mut dyn Write,
+        up: bool,
+        direction: OctaveDirection,
+    ) -> io::Result<()> {
+        match self.source {
+            Source::Noise => Ok(()),
+            _ => write!(out, "{}", octave_step(up, direction)),
+        }
+    }
+
+    // `@` is the duty cycle of the pulse channels.
+    fn write_timbre(&self, out: &mut dyn Write, timbre: u8) -> io::Result<()> {
+        match self.source {
+            Source::Pulse => write!(out, "@{}", timbre % PULSE_DUTIES),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub struct GameBoy {
+    pulse: GameBoyChannel,
+    pulse_decay: GameBoyChannel,
+    wave: GameBoyChannel,
+    noise: GameBoyChannel,
+}
+
+impl GameBoy {
+    pub fn new() -> Self {
+        GameBoy {
+            pulse: GameBoyChannel {
+                source: Source::Pulse,
+                envelope: 0,
+            },
+            pulse_decay: GameBoyChannel {
+                source: Source::Pulse,
+                envelope: DECAY_ENVELOPE,
+            },
+            wave: GameBoyChannel {
+                source: Source::Wave,
+                envelope: 0,
+            },
+            noise: GameBoyChannel {
+                source: Source::Noise,
+                envelope: NOISE_ENVELOPE,
+            },
+        }
+    }
+
+    fn notes(part: &MmlPart) -> impl Iterator<Item = u8> + '_ {
+        part.tokens.iter().filter_map(|token| match token {
+            Token::Note { key, .. } => Some(*key),
+            _ => None,
+        })
+    }
+
+    fn average_key(part: &MmlPart) -> u32 {
+        let count = Self::notes(part).count() as u32;
+        Self::notes(part).map(u32::from).sum::<u32>() / count.max(1)
+    }
+
+    // Pianos, chromatic percussion, guitars, plucked strings and percussive programs
+    // fade out instead of holding.
+    fn decays(part: &MmlPart) -> bool {
+        part.tokens
+            .iter()
+            .find_map(|token| match token {
+                Token::Timbre(timbre) => Some(*timbre),
+                _ => None,
+            })
+            .is_some_and(|timbre| matches!(timbre, 0..=15 | 24..=31 | 45 | 46 | 104..=119))
+    }
+
+    fn drop(part: &MmlPart, diagnostics: &mut Vec<Diagnostic>) {
+        diagnostics.push(Diagnostic::warning(
+            DiagnosticKind::VoiceDropped,
+            part.track,
+            0,
+            part.channel,
+            Some(part.sub_channel),
+            None,
+        ));
+    }
+}
+
+impl Default for GameBoy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MmlDialect for GameBoy {
+    fn name(&self) -> &str {
+        "gameboy"
+    }
+
+    fn min_length(&self) -> u32 {
+        64
+    }
+
+    fn initial_volume(&self) -> Option<u8> {
+        None
+    }
+
+    fn volume(&self, velocity: u8) -> u8 {
+        map_range(velocity, 0, 127, 0, 15)
+    }
+
+    fn octave(&self, key: u8) -> u8 {
+        octave_a440(key)
+    }
+
+    fn key_range(&self) -> RangeInclusive<u8> {
+        OCTAVE_KEYS
+    }
+
+    // The busiest voices win the hardware channels. Of the three melodic voices the
+    // lowest goes to the wave channel, and the busiest drum voice to noise.
+    fn arrange(
+        &self,
+        parts: &mut Vec<MmlPart>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<(), ConvertError> {
+        let mut melodic: Vec<&mut MmlPart> = Vec::new();
+        let mut drums: Vec<&mut MmlPart> = Vec::new();
+        for part in parts.iter_mut() {
+            if part.channel == DRUM_CHANNEL {
+                drums.push(part);
+            } else {
+                melodic.push(part);
+            }
+        }
+        melodic.sort_by_key(|part| std::cmp::Reverse(Self::notes(part).count()));
+        drums.sort_by_key(|part| std::cmp::Reverse(Self::notes(part).count()));
+
+        for part in melodic.iter().skip(MELODIC_CHANNELS.len()) {
+            Self::drop(part, diagnostics);
+        }
+        melodic.truncate(MELODIC_CHANNELS.len());
+        melodic.sort_by_key(|part| std::cmp::Reverse(Self::average_key(part)));
+        for (part, label) in melodic.iter_mut().zip(MELODIC_CHANNELS) {
+            part.label = Some(label.to_string());
+        }
+
+        for part in drums.iter().skip(1) {
+            Self::drop(part, diagnostics);
+        }
+        if let Some(part) = drums.first_mut() {
+            part.label = Some(NOISE_CHANNEL.to_string());
+        }
+
+        parts.retain(|part| part.label.is_some());
+        parts.sort_by(|part1, part2| part1.label.cmp(&part2.label));
+        Ok(())
+    }
+
+    fn part_dialect(&self, part: &MmlPart) -> Option<&dyn MmlDialect> {
+        Some(match part.label.as_deref()? {
+            "C" => &self.wave,
+            "D" => &self.noise,
+            _ if Self::decays(part) => &self.pulse_decay,
+            _ => &self.pulse,
+        })
+    }
+
+    fn write_document(&self, out: &mut dyn Write, result: &ConversionResult) -> io::Result<()> {
+        if let Some(title) = &result.title {
+            writeln!(out, "#TITLE {}", title)?;
+        }
+        if let Some(composer) = &result.composer {
+            writeln!(out, "#COMPOSER {}", composer)?;
+        }
+        write_labelled_parts(self, out, &result.parts, " ")
+    }
+}
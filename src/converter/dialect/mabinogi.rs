@@ -0,0 +1,186 @@
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use super::{map_range, octave_a440, MmlDialect, OCTAVE_KEYS};
+use crate::converter::mml::{self, Token};
+use crate::converter::{
+    ConversionResult, ConvertError, Diagnostic, DiagnosticKind, MmlPart, Severity,
+};
+
+const PART_NAMES: [&str; 3] = ["melody", "harmony1", "harmony2"];
+pub const DEFAULT_LIMITS: [usize; 3] = [1200, 800, 500];
+const INITIAL_TEMPO: f64 = 120.0;
+
+pub struct Mabinogi {
+    limits: Vec<usize>,
+}
+
+impl Mabinogi {
+    pub fn new(limits: Vec<usize>) -> Self {
+        Mabinogi { limits }
+    }
+
+    fn render(&self, tokens: &[Token]) -> usize {
+        let mut mml: Vec<u8> = Vec::new();
+        // Writing into a Vec does not fail.
+        let _ = mml::render(tokens, false, self.octave_direction(), self, &mut mml);
+        mml.len()
+    }
+
+    // Number of whole measures of `tokens` whose MML fits into `limit` characters.
+    fn measures_fit(&self, tokens: &[Token], limit: usize) -> usize {
+        let mut fit = 0;
+        for (index, token) in tokens.iter().enumerate() {
+            if let Token::BarLine(_) = token {
+                if self.render(&tokens[..=index]) > limit {
+                    break;
+                }
+                fit += 1;
+            }
+        }
+        fit
+    }
+
+    fn measure_count(tokens: &[Token]) -> usize {
+        tokens
+            .split_inclusive(|token| matches!(token, Token::BarLine(_)))
+            .filter(|line| line.iter().any(|token| !matches!(token, Token::BarLine(_))))
+            .count()
+    }
+
+    // Keeps the first `measures` measures, nothing when `measures` is zero.
+    fn truncate(tokens: &mut Vec<Token>, measures: usize) {
+        let last_bar_line = measures.checked_sub(1).and_then(|nth| {
+            tokens
+                .iter()
+                .enumerate()
+                .filter(|(_, token)| matches!(token, Token::BarLine(_)))
+                .nth(nth)
+        });
+        tokens.truncate(last_bar_line.map_or(0, |(index, _)| index + 1));
+    }
+}
+
+impl Default for Mabinogi {
+    fn default() -> Self {
+        Self::new(DEFAULT_LIMITS.to_vec())
+    }
+}
+
+impl MmlDialect for Mabinogi {
+    fn name(&self) -> &str {
+        "mabinogi"
+    }
+
+    // Lengths go down to 64, so triplets stop at 48.
+    fn min_length(&self) -> u32 {
+        32
+    }
+
+    fn initial_volume(&self) -> Option<u8> {
+        Some(8)
+    }
+
+    fn volume(&self, velocity: u8) -> u8 {
+        map_range(velocity, 0, 127, 0, 15)
+    }
+
+    // o4c is middle C.
+    fn octave(&self, key: u8) -> u8 {
+        octave_a440(key)
+    }
+
+    fn key_range(&self) -> RangeInclusive<u8> {
+        OCTAVE_KEYS
+    }
+
+    // Instruments are chosen in game, not in the MML.
+    fn write_timbre(&self, _out: &mut dyn Write, _timbre: u8) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_comment(&self, _out: &mut dyn Write, _comment: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_bar_line(&self, _out: &mut dyn Write, _bar: u32, _comment: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    // The tempo of any part applies to the whole song, so it is kept in the melody
+    // only. Parts that do not fit their limit are cut at a measure boundary, and all
+    // parts are cut to the same number of measures so they end together.
+    fn arrange(
+        &self,
+        parts: &mut Vec<MmlPart>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<(), ConvertError> {
+        for part in parts.iter().skip(PART_NAMES.len()) {
+            diagnostics.push(Diagnostic::warning(
+                DiagnosticKind::VoiceDropped,
+                part.track,
+                0,
+                part.channel,
+                Some(part.sub_channel),
+                None,
+            ));
+        }
+        parts.truncate(PART_NAMES.len());
+
+        for (index, part) in parts.iter_mut().enumerate() {
+            let mut first_tempo = true;
+            part.tokens.retain(|token| match token {
+                Token::Tempo(tempo) if index == 0 => {
+                    let keep = !first_tempo || *tempo != INITIAL_TEMPO;
+                    first_tempo = false;
+                    keep
+                }
+                Token::Tempo(_) => false,
+                _ => true,
+            });
+        }
+
+        let measures = parts
+            .iter()
+            .map(|part| Self::measure_count(&part.tokens))
+            .max()
+            .unwrap_or(0);
+        let mut fit = measures;
+        for (index, part) in parts.iter().enumerate() {
+            let limit = self.limits.get(index).copied().unwrap_or(usize::MAX);
+            if self.render(&part.tokens) > limit {
+                let part_fit = self.measures_fit(&part.tokens, limit);
+                diagnostics.push(
+                    Diagnostic::warning(
+                        DiagnosticKind::PartTruncated,
+                        part.track,
+                        0,
+                        part.channel,
+                        Some(part.sub_channel),
+                        None,
+                    )
+                    .with_message(format!(
+                        "{} fits {} of {} measures in {} characters.",
+                        PART_NAMES[index], part_fit, measures, limit
+                    )),
+                );
+                fit = fit.min(part_fit);
+            }
+        }
+        if fit < measures {
+            for part in parts.iter_mut() {
+                Self::truncate(&mut part.tokens, fit);
+            }
+        }
+        diagnostics.push(
+            Diagnostic::song(Severity::Info, DiagnosticKind::MeasuresFit, 0)
+                .with_message(format!("{} of {} measures fit.", fit, measures)),
+        );
+        Ok(())
+    }
+
+    fn write_document(&self, out: &mut dyn Write, result: &ConversionResult) -> io::Result<()> {
+        let parts: Vec<&str> = result.parts.iter().map(|part| part.mml.as_str()).collect();
+        writeln!(out, "MML@{};", parts.join(","))
+    }
+}
@@ -0,0 +1,239 @@
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use super::{map_range, octave_a440, MmlDialect, OCTAVE_KEYS};
+use crate::converter::length::Length;
+use crate::converter::{ConversionResult, ConvertError, Diagnostic, DiagnosticKind, MmlPart};
+
+const VOICES: usize = 3;
+const LINE_LIMIT: usize = 255;
+const LINE_NUMBER_START: u32 = 10;
+const LINE_NUMBER_STEP: u32 = 10;
+const TEMPO_MIN: f64 = 32.0;
+const TEMPO_MAX: f64 = 255.0;
+// N96 is the highest numbered note, one above O8B.
+const NUMBERED_KEY_MAX: u8 = 120;
+const NUMBERED_KEY_OFFSET: u8 = 24;
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C+", "D", "D+", "E", "F", "F+", "G", "G+", "A", "A+", "B",
+];
+
+pub struct Msx;
+
+impl Msx {
+    // Splits before a command letter so that no piece is longer than `budget`.
+    fn split_commands(mml: &str, budget: usize) -> Vec<String> {
+        let mut pieces: Vec<String> = Vec::new();
+        let mut piece = String::new();
+        let mut command = String::new();
+        for c in mml.chars() {
+            if !c.is_ascii_digit() && c != '.' && c != '+' && !command.is_empty() {
+                if !piece.is_empty() && piece.len() + command.len() > budget {
+                    pieces.push(std::mem::take(&mut piece));
+                }
+                piece.push_str(&std::mem::take(&mut command));
+            }
+            command.push(c);
+        }
+        if !piece.is_empty() && piece.len() + command.len() > budget {
+            pieces.push(std::mem::take(&mut piece));
+        }
+        piece.push_str(&command);
+        if !piece.is_empty() {
+            pieces.push(piece);
+        }
+        pieces
+    }
+
+    fn write_statement(
+        out: &mut dyn Write,
+        line_number: &mut u32,
+        strings: &[String],
+    ) -> io::Result<()> {
+        let count = strings
+            .iter()
+            .rposition(|string| !string.is_empty())
+            .map_or(0, |index| index + 1);
+        if count == 0 {
+            return Ok(());
+        }
+        let strings: Vec<String> = strings[..count]
+            .iter()
+            .map(|string| format!("\"{}\"", string))
+            .collect();
+        writeln!(out, "{} PLAY {}", line_number, strings.join(","))?;
+        *line_number += LINE_NUMBER_STEP;
+        Ok(())
+    }
+}
+
+impl MmlDialect for Msx {
+    fn name(&self) -> &str {
+        "msx"
+    }
+
+    // L accepts 1-64, so triplets stop at 48.
+    fn min_length(&self) -> u32 {
+        32
+    }
+
+    fn supports_tempo_in_tie(&self) -> bool {
+        false
+    }
+
+    fn initial_volume(&self) -> Option<u8> {
+        Some(8)
+    }
+
+    fn volume(&self, velocity: u8) -> u8 {
+        map_range(velocity, 0, 127, 0, 15)
+    }
+
+    fn octave(&self, key: u8) -> u8 {
+        octave_a440(key)
+    }
+
+    fn key_range(&self) -> RangeInclusive<u8> {
+        *OCTAVE_KEYS.start()..=NUMBERED_KEY_MAX
+    }
+
+    // Keys above O8 are written as numbered notes, which take the length from L.
+    fn write_note(
+        &self,
+        out: &mut dyn Write,
+        key: u8,
+        lengths: &[Length],
+        default: Option<Length>,
+    ) -> io::Result<()> {
+        if OCTAVE_KEYS.contains(&key) || !self.key_range().contains(&key) {
+            for length in lengths.iter() {
+                self.write_note_name(out, key)?;
+                self.write_length(out, length, default)?;
+            }
+            return Ok(());
+        }
+        let mut current = default;
+        for length in lengths.iter() {
+            if current != Some(*length) {
+                self.write_default_length(out, length)?;
+                current = Some(*length);
+            }
+            write!(out, "N{}", key - NUMBERED_KEY_OFFSET)?;
+        }
+        match default {
+            Some(default) if current != Some(default) => self.write_default_length(out, &default),
+            _ => Ok(()),
+        }
+    }
+
+    fn write_note_name(&self, out: &mut dyn Write, key: u8) -> io::Result<()> {
+        write!(out, "{}", NOTE_NAMES[(key % 12) as usize])
+    }
+
+    // PLAY has no tie, the segments are played as separate notes.
+    fn write_tie(&self, _out: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_rest(
+        &self,
+        out: &mut dyn Write,
+        length: &Length,
+        default: Option<Length>,
+    ) -> io::Result<()> {
+        write!(out, "R")?;
+        self.write_length(out, length, default)
+    }
+
+    fn write_tempo(&self, out: &mut dyn Write, tempo: f64) -> io::Result<()> {
+        write!(out, "T{}", tempo.clamp(TEMPO_MIN, TEMPO_MAX))
+    }
+
+    fn write_volume(&self, out: &mut dyn Write, volume: u8) -> io::Result<()> {
+        write!(out, "V{}", volume)
+    }
+
+    fn write_octave(&self, out: &mut dyn Write, octave: u8) -> io::Result<()> {
+        write!(out, "O{}", octave)
+    }
+
+    // The PSG has a single tone.
+    fn write_timbre(&self, _out: &mut dyn Write, _timbre: u8) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_default_length(&self, out: &mut dyn Write, length: &Length) -> io::Result<()> {
+        write!(out, "L{}", length)
+    }
+
+    fn write_bar_line(&self, out: &mut dyn Write, _bar: u32, _comment: bool) -> io::Result<()> {
+        writeln!(out)
+    }
+
+    fn arrange(
+        &self,
+        parts: &mut Vec<MmlPart>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<(), ConvertError> {
+        for part in parts.iter().skip(VOICES) {
+            diagnostics.push(Diagnostic::warning(
+                DiagnosticKind::VoiceDropped,
+                part.track,
+                0,
+                part.channel,
+                Some(part.sub_channel),
+                None,
+            ));
+        }
+        parts.truncate(VOICES);
+        Ok(())
+    }
+
+    // Every PLAY statement holds the same measures of all voices, so no voice runs
+    // ahead of the others while BASIC waits for room in the play queues.
+    fn write_document(&self, out: &mut dyn Write, result: &ConversionResult) -> io::Result<()> {
+        let voices = result.parts.len().max(1);
+        let budget = (LINE_LIMIT - format!("{} PLAY ", u16::MAX).len() - voices * 3) / voices;
+        let measures: Vec<Vec<&str>> = result
+            .parts
+            .iter()
+            .map(|part| part.mml.trim_end_matches('\n').split('\n').collect())
+            .collect();
+        let measure_count = measures.iter().map(|lines| lines.len()).max().unwrap_or(0);
+        let join = |start: usize, end: usize| -> Vec<String> {
+            measures
+                .iter()
+                .map(|lines| lines[start.min(lines.len())..end.min(lines.len())].concat())
+                .collect()
+        };
+        let fits = |strings: &[String]| strings.iter().all(|string| string.len() <= budget);
+
+        let mut line_number = LINE_NUMBER_START;
+        let mut start = 0;
+        while start < measure_count {
+            let mut end = start + 1;
+            while end < measure_count && fits(&join(start, end + 1)) {
+                end += 1;
+            }
+            let strings = join(start, end);
+            if fits(&strings) {
+                Self::write_statement(out, &mut line_number, &strings)?;
+            } else {
+                let pieces: Vec<Vec<String>> = strings
+                    .iter()
+                    .map(|string| Self::split_commands(string, budget))
+                    .collect();
+                let count = pieces.iter().map(|piece| piece.len()).max().unwrap_or(0);
+                for index in 0..count {
+                    let strings: Vec<String> = pieces
+                        .iter()
+                        .map(|piece| piece.get(index).cloned().unwrap_or_default())
+                        .collect();
+                    Self::write_statement(out, &mut line_number, &strings)?;
+                }
+            }
+            start = end;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use super::{write_labelled_parts, MmlDialect, DRUM_CHANNEL, OCTAVE_MAX};
+use crate::converter::mml::Token;
+use crate::converter::{ConversionResult, ConvertError, Diagnostic, DiagnosticKind, MmlPart};
+
+const WHOLE_NOTE_CLOCKS: u32 = 192;
+const OPM_TRACKS: [&str; 8] = ["A", "B", "C", "D", "E", "F", "G", "H"];
+const PCM_TRACKS: [&str; 8] = ["P", "Q", "R", "S", "T", "U", "V", "W"];
+
+pub struct Mxdrv;
+
+impl Mxdrv {
+    fn note_count(part: &MmlPart) -> usize {
+        part.tokens
+            .iter()
+            .filter(|token| matches!(token, Token::Note { .. }))
+            .count()
+    }
+
+    // When a source has fewer tracks than voices, the voices with the most notes are
+    // kept and take the tracks in their original order.
+    fn assign(
+        parts: &mut [MmlPart],
+        drums: bool,
+        tracks: &[&str],
+        source: &str,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let mut voices: Vec<(usize, &mut MmlPart)> = parts
+            .iter_mut()
+            .filter(|part| (part.channel == DRUM_CHANNEL) == drums)
+            .enumerate()
+            .collect();
+        voices.sort_by_key(|(_, part)| std::cmp::Reverse(Self::note_count(part)));
+        for (_, part) in voices.iter().skip(tracks.len()) {
+            diagnostics.push(
+                Diagnostic::warning(
+                    DiagnosticKind::VoiceDropped,
+                    part.track,
+                    0,
+                    part.channel,
+                    Some(part.sub_channel),
+                    None,
+                )
+                .with_message(format!(
+                    "{} has {} voices, tracks {}-{} are in use.",
+                    source,
+                    tracks.len(),
+                    tracks[0],
+                    tracks[tracks.len() - 1]
+                )),
+            );
+        }
+        voices.truncate(tracks.len());
+        voices.sort_by_key(|(index, _)| *index);
+        for ((_, part), track) in voices.iter_mut().zip(tracks) {
+            part.label = Some(track.to_string());
+        }
+    }
+}
+
+impl MmlDialect for Mxdrv {
+    fn name(&self) -> &str {
+        "mxdrv"
+    }
+
+    fn min_length(&self) -> u32 {
+        64
+    }
+
+    fn supports_length(&self, denominator: u32) -> bool {
+        WHOLE_NOTE_CLOCKS.is_multiple_of(denominator)
+    }
+
+    fn initial_volume(&self) -> Option<u8> {
+        None
+    }
+
+    fn volume(&self, velocity: u8) -> u8 {
+        velocity
+    }
+
+    // o4a is 440Hz.
+    fn octave(&self, key: u8) -> u8 {
+        (key / 12).saturating_sub(1).min(OCTAVE_MAX)
+    }
+
+    // o0 starts at key 12.
+    fn key_range(&self) -> RangeInclusive<u8> {
+        12..=(OCTAVE_MAX + 2) * 12 - 1
+    }
+
+    fn write_tempo(&self, out: &mut dyn Write, tempo: f64) -> io::Result<()> {
+        write!(out, "(t{})", tempo)
+    }
+
+    fn write_volume(&self, out: &mut dyn Write, volume: u8) -> io::Result<()> {
+        write!(out, "@v{}", volume)
+    }
+
+    fn write_comment(&self, out: &mut dyn Write, comment: &str) -> io::Result<()> {
+        write!(out, "/* {} */", comment.trim())
+    }
+
+    // Melodic voices go to the OPM tracks, drums of MIDI channel 10 to the PCM tracks.
+    fn arrange(
+        &self,
+        parts: &mut Vec<MmlPart>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<(), ConvertError> {
+        Self::assign(parts, false, &OPM_TRACKS, "OPM", diagnostics);
+        Self::assign(parts, true, &PCM_TRACKS, "PCM", diagnostics);
+        parts.retain(|part| part.label.is_some());
+        parts.sort_by(|part1, part2| part1.label.cmp(&part2.label));
+        Ok(())
+    }
+
+    fn write_document(&self, out: &mut dyn Write, result: &ConversionResult) -> io::Result<()> {
+        if let Some(title) = &result.title {
+            writeln!(out, "#title \"{}\"", title)?;
+        }
+        write_labelled_parts(self, out, &result.parts, " ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::{ConvertOptions, Converter};
+    use crate::reverse;
+    use crate::OctaveDirection;
+
+    #[test]
+    fn key_range() {
+        for (key, octave, in_range) in [
+            (0, 0, false),
+            (12, 0, true),
+            (69, 4, true),
+            (119, 8, true),
+            (120, 8, false),
+        ] {
+            assert_eq!(Mxdrv.octave(key), octave, "key {}", key);
+            assert_eq!(Mxdrv.key_range().contains(&key), in_range, "key {}", key);
+        }
+    }
+
+    #[test]
+    fn key_out_of_range() {
+        let song = reverse::parse("o0c o1c o9b o10c", 480, OctaveDirection::GreaterUp).unwrap();
+        let mut data: Vec<u8> = Vec::new();
+        reverse::write_smf(&song, &mut data).unwrap();
+        let result = Converter::with_dialect(data, ConvertOptions::default(), Box::new(Mxdrv))
+            .convert()
+            .unwrap();
+        let keys: Vec<Option<u8>> = result
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.kind == DiagnosticKind::KeyOutOfRange)
+            .map(|diagnostic| diagnostic.key)
+            .collect();
+        assert_eq!(keys, vec![Some(0), Some(120)]);
+    }
+}
@@ -0,0 +1,325 @@
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use super::{
+    map_range, octave_a440, octave_step, write_labelled_lines, write_part_header, MmlDialect,
+    DRUM_CHANNEL, OCTAVE_KEYS,
+};
+use crate::converter::length::Length;
+use crate::converter::{
+    ConversionResult, ConvertError, Diagnostic, DiagnosticKind, MmlPart, OctaveDirection,
+};
+
+const WHOLE_NOTE_CLOCKS: u32 = 96;
+const FM_PARTS: [&str; 6] = ["A", "B", "C", "D", "E", "F"];
+const SSG_PARTS: [&str; 3] = ["G", "H", "I"];
+const ADPCM_PARTS: [&str; 1] = ["J"];
+const RHYTHM_PART: &str = "K";
+
+// How melodic voices are assigned to FM and SSG parts. The ADPCM part takes the
+// voices left over in every policy.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PmdPolicy {
+    FmFirst,
+    SsgFirst,
+    // Sub-channel 0 of every channel to FM, chord voices to SSG.
+    SubChannelSsg,
+}
+
+impl PmdPolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fm-first" => Some(PmdPolicy::FmFirst),
+            "ssg-first" => Some(PmdPolicy::SsgFirst),
+            "sub-ssg" => Some(PmdPolicy::SubChannelSsg),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Fm,
+    Ssg,
+    Adpcm,
+    Rhythm,
+}
+
+// Syntax of one sound source. Volumes arrive as MIDI velocity and are scaled to
+// the fine `V` range of the source.
+struct PmdPart {
+    source: Source,
+}
+
+impl MmlDialect for PmdPart {
+    fn name(&self) -> &str {
+        "pmd"
+    }
+
+    fn write_length(
+        &self,
+        out: &mut dyn Write,
+        length: &Length,
+        default: Option<Length>,
+    ) -> io::Result<()> {
+        // Rhythm patterns are written one per measure, so no default length carries over.
+        if self.source == Source::Rhythm || default != Some(*length) {
+            write!(out, "{}", length)?;
+        }
+        Ok(())
+    }
+
+    fn write_note(
+        &self,
+        out: &mut dyn Write,
+        key: u8,
+        lengths: &[Length],
+        default: Option<Length>,
+    ) -> io::Result<()> {
+        if self.source != Source::Rhythm {
+            for (index, length) in lengths.iter().enumerate() {
+                if index > 0 {
+                    self.write_tie(out)?;
+                }
+                self.write_note_name(out, key)?;
+                self.write_length(out, length, default)?;
+            }
+            return Ok(());
+        }
+        // Rhythm source commands take no time, the length is spent as rests.
+        let command = match key {
+            35 | 36 => "b",
+            38 | 40 => "s",
+            41 | 43 | 45 | 47 | 48 | 50 => "t",
+            42 | 44 | 46 => "h",
+            49 | 51 | 52 | 55 | 57 | 59 => "c",
+            _ => "i",
+        };
+        write!(out, "\\{}", command)?;
+        for length in lengths.iter() {
+            self.write_rest(out, length, default)?;
+        }
+        Ok(())
+    }
+
+    fn write_volume(&self, out: &mut dyn Write, volume: u8) -> io::Result<()> {
+        match self.source {
+            Source::Fm => write!(out, "V{}", volume),
+            Source::Ssg => write!(out, "V{}", map_range(volume, 0, 127, 0, 15)),
+            Source::Adpcm => write!(out, "V{}", volume as u16 * 2),
+            Source::Rhythm => Ok(()),
+        }
+    }
+
+    fn write_octave(&self, out: &mut dyn Write, octave: u8) -> io::Result<()> {
+        match self.source {
+            Source::Rhythm => Ok(()),
+            _ => write!(out, "o{}", octave),
+        }
+    }
+
+    fn write_octave_step(
+        &self,
+        out: &mut dyn Write,
+        up: bool,
+        direction: OctaveDirection,
+    ) -> io::Result<()> {
+        match self.source {
+            Source::Rhythm => Ok(()),
+            _ => write!(out, "{}", octave_step(up, direction)),
+        }
+    }
+
+    // SSG has no tone numbers, only envelopes.
+    fn write_timbre(&self, out: &mut dyn Write, timbre: u8) -> io::Result<()> {
+        match self.source {
+            Source::Fm | Source::Adpcm => write!(out, "@{}", timbre),
+            _ => Ok(()),
+        }
+    }
+
+    fn write_tempo(&self, out: &mut dyn Write, tempo: f64) -> io::Result<()> {
+        match self.source {
+            Source::Rhythm => Ok(()),
+            _ => write!(out, "t{}", tempo),
+        }
+    }
+
+    fn write_default_length(&self, out: &mut dyn Write, length: &Length) -> io::Result<()> {
+        match self.source {
+            Source::Rhythm => Ok(()),
+            _ => write!(out, "l{}", length),
+        }
+    }
+
+    fn write_bar_line(&self, out: &mut dyn Write, _bar: u32, _comment: bool) -> io::Result<()> {
+        writeln!(out)
+    }
+}
+
+pub struct Pmd {
+    policy: PmdPolicy,
+    fm: PmdPart,
+    ssg: PmdPart,
+    adpcm: PmdPart,
+    rhythm: PmdPart,
+}
+
+impl Pmd {
+    pub fn new(policy: PmdPolicy) -> Self {
+        Pmd {
+            policy,
+            fm: PmdPart { source: Source::Fm },
+            ssg: PmdPart {
+                source: Source::Ssg,
+            },
+            adpcm: PmdPart {
+                source: Source::Adpcm,
+            },
+            rhythm: PmdPart {
+                source: Source::Rhythm,
+            },
+        }
+    }
+
+    fn source(label: &str) -> Source {
+        if FM_PARTS.contains(&label) {
+            Source::Fm
+        } else if SSG_PARTS.contains(&label) {
+            Source::Ssg
+        } else if ADPCM_PARTS.contains(&label) {
+            Source::Adpcm
+        } else {
+            Source::Rhythm
+        }
+    }
+
+    fn write_rhythm(out: &mut dyn Write, mml: &str) -> io::Result<()> {
+        let mut patterns: Vec<&str> = Vec::new();
+        let mut sequence = String::new();
+        for line in mml.lines().filter(|line| !line.is_empty()) {
+            let index = match patterns.iter().position(|pattern| *pattern == line) {
+                Some(index) => index,
+                None => {
+                    patterns.push(line);
+                    patterns.len() - 1
+                }
+            };
+            sequence.push_str(&format!("R{}", index));
+        }
+        for (index, pattern) in patterns.iter().enumerate() {
+            writeln!(out, "R{}\t{}", index, pattern)?;
+        }
+        writeln!(out, "{}\t{}", RHYTHM_PART, sequence)
+    }
+}
+
+impl MmlDialect for Pmd {
+    fn name(&self) -> &str {
+        "pmd"
+    }
+
+    fn min_length(&self) -> u32 {
+        32
+    }
+
+    fn supports_length(&self, denominator: u32) -> bool {
+        WHOLE_NOTE_CLOCKS.is_multiple_of(denominator)
+    }
+
+    fn initial_volume(&self) -> Option<u8> {
+        None
+    }
+
+    fn initial_octave(&self) -> Option<u8> {
+        None
+    }
+
+    fn volume(&self, velocity: u8) -> u8 {
+        velocity
+    }
+
+    fn octave(&self, key: u8) -> u8 {
+        octave_a440(key)
+    }
+
+    fn key_range(&self) -> RangeInclusive<u8> {
+        OCTAVE_KEYS
+    }
+
+    fn arrange(
+        &self,
+        parts: &mut Vec<MmlPart>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<(), ConvertError> {
+        let mut fm = FM_PARTS.iter();
+        let mut ssg = SSG_PARTS.iter();
+        let mut adpcm = ADPCM_PARTS.iter();
+        let mut rhythm = [RHYTHM_PART].into_iter();
+        for part in parts.iter_mut() {
+            let label = if part.channel == DRUM_CHANNEL {
+                rhythm.next()
+            } else {
+                let ssg_first = match self.policy {
+                    PmdPolicy::FmFirst => false,
+                    PmdPolicy::SsgFirst => true,
+                    PmdPolicy::SubChannelSsg => part.sub_channel > 0,
+                };
+                if ssg_first {
+                    ssg.next().or_else(|| fm.next())
+                } else {
+                    fm.next().or_else(|| ssg.next())
+                }
+                .or_else(|| adpcm.next())
+                .copied()
+            };
+            match label {
+                Some(label) => part.label = Some(label.to_string()),
+                None => diagnostics.push(Diagnostic::warning(
+                    DiagnosticKind::VoiceDropped,
+                    part.track,
+                    0,
+                    part.channel,
+                    Some(part.sub_channel),
+                    None,
+                )),
+            }
+        }
+        parts.retain(|part| part.label.is_some());
+        parts.sort_by(|part1, part2| part1.label.cmp(&part2.label));
+        Ok(())
+    }
+
+    fn part_dialect(&self, part: &MmlPart) -> Option<&dyn MmlDialect> {
+        let label = part.label.as_deref()?;
+        Some(match Self::source(label) {
+            Source::Fm => &self.fm,
+            Source::Ssg => &self.ssg,
+            Source::Adpcm => &self.adpcm,
+            Source::Rhythm => &self.rhythm,
+        })
+    }
+
+    fn write_document(&self, out: &mut dyn Write, result: &ConversionResult) -> io::Result<()> {
+        writeln!(
+            out,
+            "#Title\t{}",
+            result.title.as_deref().unwrap_or_default()
+        )?;
+        writeln!(
+            out,
+            "#Composer\t{}",
+            result.composer.as_deref().unwrap_or_default()
+        )?;
+        for part in result.parts.iter() {
+            let label = part.label.as_deref().unwrap_or_default();
+            write_part_header(self, out, part)?;
+            if Self::source(label) == Source::Rhythm {
+                Self::write_rhythm(out, &part.mml)?;
+            } else {
+                write_labelled_lines(out, label, "\t", &part.mml)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use super::{map_range, write_labelled_parts, MmlDialect, DRUM_CHANNEL};
+use crate::converter::length::Length;
+use crate::converter::mml::Token;
+use crate::converter::{ConversionResult, ConvertError, Diagnostic, MmlPart};
+
+// 2A03 tracks: two pulse and the triangle for melodic voices, noise and DPCM for drums.
+const MELODIC_TRACKS: [&str; 3] = ["A", "B", "C"];
+const DRUM_TRACKS: [&str; 2] = ["D", "E"];
+const PULSE_DUTIES: u8 = 4;
+// ppmck octaves are o0-o7.
+const OCTAVE_MAX: u8 = 7;
+
+pub struct Ppmck;
+
+impl Ppmck {
+    fn assign(parts: &mut [MmlPart], drums: bool, tracks: &[&str]) -> Result<(), ConvertError> {
+        for (index, part) in parts
+            .iter_mut()
+            .filter(|part| (part.channel == DRUM_CHANNEL) == drums)
+            .enumerate()
+        {
+            match tracks.get(index) {
+                Some(track) => part.label = Some(track.to_string()),
+                None => {
+                    return Err(ConvertError::VoicesExhausted {
+                        dialect: format!("ppmck tracks {}", tracks.join("")),
+                        track: part.track,
+                        channel: part.channel,
+                        sub_channel: part.sub_channel,
+                        available: tracks.len(),
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MmlDialect for Ppmck {
+    fn name(&self) -> &str {
+        "ppmck"
+    }
+
+    fn initial_volume(&self) -> Option<u8> {
+        None
+    }
+
+    fn initial_octave(&self) -> Option<u8> {
+        None
+    }
+
+    fn volume(&self, velocity: u8) -> u8 {
+        map_range(velocity, 0, 127, 0, 15)
+    }
+
+    // o4a is 440Hz.
+    fn octave(&self, key: u8) -> u8 {
+        (key / 12).saturating_sub(1).min(OCTAVE_MAX)
+    }
+
+    // o0 starts at key 12.
+    fn key_range(&self) -> RangeInclusive<u8> {
+        12..=(OCTAVE_MAX + 2) * 12 - 1
+    }
+
+    fn write_note(
+        &self,
+        out: &mut dyn Write,
+        key: u8,
+        lengths: &[Length],
+        default: Option<Length>,
+    ) -> io::Result<()> {
+        self.write_note_name(out, key)?;
+        for (index, length) in lengths.iter().enumerate() {
+            if index > 0 {
+                write!(out, "^{}", length)?;
+            } else {
+                self.write_length(out, length, default)?;
+            }
+        }
+        Ok(())
+    }
+
+    // `^` goes in front of the tied length, the note is not written again.
+    fn write_tie(&self, _out: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_tied_note(
+        &self,
+        out: &mut dyn Write,
+        _key: u8,
+        lengths: &[Length],
+        _default: Option<Length>,
+    ) -> io::Result<()> {
+        for length in lengths.iter() {
+            write!(out, "^{}", length)?;
+        }
+        Ok(())
+    }
+
+    // `@` selects the duty cycle on the pulse tracks. The other tracks have none.
+    fn arrange(
+        &self,
+        parts: &mut Vec<MmlPart>,
+        _diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<(), ConvertError> {
+        Self::assign(parts, false, &MELODIC_TRACKS)?;
+        Self::assign(parts, true, &DRUM_TRACKS)?;
+        for part in parts.iter_mut() {
+            let pulse = matches!(part.label.as_deref(), Some("A") | Some("B"));
+            part.tokens.retain_mut(|token| match token {
+                Token::Timbre(timbre) => {
+                    *timbre %= PULSE_DUTIES;
+                    pulse
+                }
+                _ => true,
+            });
+        }
+        parts.sort_by(|part1, part2| part1.label.cmp(&part2.label));
+        Ok(())
+    }
+
+    fn write_document(&self, out: &mut dyn Write, result: &ConversionResult) -> io::Result<()> {
+        writeln!(
+            out,
+            "#TITLE {}",
+            result.title.as_deref().unwrap_or_default()
+        )?;
+        writeln!(
+            out,
+            "#COMPOSER {}",
+            result.composer.as_deref().unwrap_or_default()
+        )?;
+        write_labelled_parts(self, out, &result.parts, " ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::{ConvertOptions, Converter, DiagnosticKind};
+    use crate::reverse;
+    use crate::OctaveDirection;
+
+    #[test]
+    fn key_range() {
+        for (key, octave, in_range) in [
+            (0, 0, false),
+            (12, 0, true),
+            (69, 4, true),
+            (107, 7, true),
+            (108, 7, false),
+            (120, 7, false),
+        ] {
+            assert_eq!(Ppmck.octave(key), octave, "key {}", key);
+            assert_eq!(Ppmck.key_range().contains(&key), in_range, "key {}", key);
+        }
+    }
+
+    #[test]
+    fn key_out_of_range() {
+        let song = reverse::parse("o0c o1c o8b o9c o10c", 480, OctaveDirection::GreaterUp).unwrap();
+        let mut data: Vec<u8> = Vec::new();
+        reverse::write_smf(&song, &mut data).unwrap();
+        let result = Converter::with_dialect(data, ConvertOptions::default(), Box::new(Ppmck))
+            .convert()
+            .unwrap();
+        let keys: Vec<Option<u8>> = result
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.kind == DiagnosticKind::KeyOutOfRange)
+            .map(|diagnostic| diagnostic.key)
+            .collect();
+        assert_eq!(keys, vec![Some(0), Some(108), Some(120)]);
+    }
+
+    #[test]
+    fn tie_across_tempo_change() {
+        let song = reverse::parse("t120 o5 c2&t90c2", 480, OctaveDirection::GreaterUp).unwrap();
+        let mut data: Vec<u8> = Vec::new();
+        reverse::write_smf(&song, &mut data).unwrap();
+        let result = Converter::with_dialect(data, ConvertOptions::default(), Box::new(Ppmck))
+            .convert()
+            .unwrap();
+        assert!(
+            result.parts[0].mml.contains("c2t90^2"),
+            "{}",
+            result.parts[0].mml
+        );
+    }
+}
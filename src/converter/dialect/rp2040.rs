@@ -0,0 +1,9 @@
+use super::MmlDialect;
+
+pub struct Rp2040;
+
+impl MmlDialect for Rp2040 {
+    fn name(&self) -> &str {
+        "rp2040"
+    }
+}
@@ -0,0 +1,133 @@
+use std::io::{self, Write};
+
+use super::MmlDialect;
+use crate::converter::length::Length;
+use crate::converter::ConversionResult;
+
+// Notes are measured exactly, so the gate time is always the full length.
+const GATE_TIME: u8 = 100;
+
+pub struct Sakura;
+
+impl MmlDialect for Sakura {
+    fn name(&self) -> &str {
+        "sakura"
+    }
+
+    fn tempo_decimals(&self) -> u8 {
+        2
+    }
+
+    fn initial_volume(&self) -> Option<u8> {
+        Some(100)
+    }
+
+    fn initial_octave(&self) -> Option<u8> {
+        Some(5)
+    }
+
+    fn volume(&self, velocity: u8) -> u8 {
+        velocity
+    }
+
+    // `&` is a slur in Sakura, `^` extends the length.
+    fn write_note(
+        &self,
+        out: &mut dyn Write,
+        key: u8,
+        lengths: &[Length],
+        default: Option<Length>,
+    ) -> io::Result<()> {
+        self.write_note_name(out, key)?;
+        for (index, length) in lengths.iter().enumerate() {
+            if index > 0 {
+                write!(out, "^{}", length)?;
+            } else {
+                self.write_length(out, length, default)?;
+            }
+        }
+        Ok(())
+    }
+
+    // `^` goes in front of the tied length, the note is not written again.
+    fn write_tie(&self, _out: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_tied_note(
+        &self,
+        out: &mut dyn Write,
+        _key: u8,
+        lengths: &[Length],
+        _default: Option<Length>,
+    ) -> io::Result<()> {
+        for length in lengths.iter() {
+            write!(out, "^{}", length)?;
+        }
+        Ok(())
+    }
+
+    fn write_tempo(&self, out: &mut dyn Write, tempo: f64) -> io::Result<()> {
+        write!(out, "Tempo({})", tempo)
+    }
+
+    // Programs are counted from 1.
+    fn write_timbre(&self, out: &mut dyn Write, timbre: u8) -> io::Result<()> {
+        write!(out, "音色({})", timbre as u16 + 1)
+    }
+
+    fn write_comment(&self, out: &mut dyn Write, comment: &str) -> io::Result<()> {
+        write!(out, "//{}", comment)
+    }
+
+    fn write_document(&self, out: &mut dyn Write, result: &ConversionResult) -> io::Result<()> {
+        if let Some(title) = &result.title {
+            writeln!(out, "曲名{{\"{}\"}}", title)?;
+        }
+        if let Some(composer) = &result.composer {
+            writeln!(out, "作者{{\"{}\"}}", composer)?;
+        }
+        for (index, part) in result.parts.iter().enumerate() {
+            self.write_comment(
+                out,
+                &format!(
+                    " Track:{} Channel:{} Sub:{}",
+                    part.track, part.channel, part.sub_channel
+                ),
+            )?;
+            writeln!(out)?;
+            writeln!(
+                out,
+                "Track({}) Channel({}) q{}",
+                index + 1,
+                part.channel + 1,
+                GATE_TIME
+            )?;
+            writeln!(out, "{}", part.mml)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::{ConvertOptions, Converter};
+    use crate::reverse;
+    use crate::OctaveDirection;
+
+    #[test]
+    fn tie_across_tempo_change() {
+        let song = reverse::parse("t120 o5 c2&t90c2", 480, OctaveDirection::GreaterUp).unwrap();
+        let mut data: Vec<u8> = Vec::new();
+        reverse::write_smf(&song, &mut data).unwrap();
+        let result = Converter::with_dialect(data, ConvertOptions::default(), Box::new(Sakura))
+            .convert()
+            .unwrap();
+        assert!(
+            result.parts[0].mml.contains("c2Tempo(90)^2"),
+            "{}",
+            result.parts[0].mml
+        );
+    }
+}
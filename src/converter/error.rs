@@ -0,0 +1,150 @@
+use std::fmt;
+use std::io;
+
+use crate::reverse::MmlError;
+
+#[derive(Debug)]
+pub enum ConvertError {
+    Smf(midly::Error),
+    Io(io::Error),
+    TimecodeUnsupported {
+        frames_per_second: f32,
+        ticks_per_frame: u8,
+    },
+    SubChannelExhausted {
+        track: usize,
+        tick: u32,
+        channel: u8,
+    },
+    IntegrityFailed {
+        track: usize,
+        tick: u32,
+        channel: u8,
+        sub_channel: u8,
+    },
+    CorrectionFailed {
+        track: usize,
+        tick: u32,
+        channel: u8,
+        sub_channel: u8,
+    },
+    VoicesExhausted {
+        dialect: String,
+        track: usize,
+        channel: u8,
+        sub_channel: u8,
+        available: usize,
+    },
+    VerifyUnsupported {
+        dialect: String,
+    },
+    GridUnsupported {
+        grid: u32,
+    },
+    TupletUnsupported {
+        notes: u32,
+    },
+    Verify(MmlError),
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConvertError::Smf(err) => write!(f, "Failed to parse MIDI file: {}", err),
+            ConvertError::Io(err) => write!(f, "{}", err),
+            ConvertError::TimecodeUnsupported {
+                frames_per_second,
+                ticks_per_frame,
+            } => write!(
+                f,
+                "Frames per second: {} Ticks per frame: {} Timecode timing cannot be converted.",
+                frames_per_second, ticks_per_frame
+            ),
+            ConvertError::SubChannelExhausted {
+                track,
+                tick,
+                channel,
+            } => write!(
+                f,
+                "Track:{} Tick:{} Channel:{} Failed to get subchannel.",
+                track, tick, channel
+            ),
+            ConvertError::IntegrityFailed {
+                track,
+                tick,
+                channel,
+                sub_channel,
+            } => write!(
+                f,
+                "Track:{} Tick:{} Channel:{} Sub:{} Sound integrity failed.",
+                track, tick, channel, sub_channel
+            ),
+            ConvertError::CorrectionFailed {
+                track,
+                tick,
+                channel,
+                sub_channel,
+            } => write!(
+                f,
+                "Track:{} Tick:{} Channel:{} Sub:{} Sound correction failed.",
+                track, tick, channel, sub_channel
+            ),
+            ConvertError::VoicesExhausted {
+                dialect,
+                track,
+                channel,
+                sub_channel,
+                available,
+            } => write!(
+                f,
+                "Track:{} Channel:{} Sub:{} No voice left, {} provides {}.",
+                track, channel, sub_channel, dialect, available
+            ),
+            ConvertError::VerifyUnsupported { dialect } => write!(
+                f,
+                "Dialect:{} Verification needs MML that can be read back (rp2040).",
+                dialect
+            ),
+            ConvertError::GridUnsupported { grid } => write!(
+                f,
+                "Grid:1/{} Quantisation grid cannot be written with the available lengths.",
+                grid
+            ),
+            ConvertError::TupletUnsupported { notes } => write!(
+                f,
+                "Tuplet:{} Tuplets need 3 to 31 notes that are not a power of two.",
+                notes
+            ),
+            ConvertError::Verify(err) => write!(f, "Failed to read back MML: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ConvertError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConvertError::Smf(err) => Some(err),
+            ConvertError::Io(err) => Some(err),
+            ConvertError::Verify(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<midly::Error> for ConvertError {
+    fn from(err: midly::Error) -> Self {
+        ConvertError::Smf(err)
+    }
+}
+
+impl From<io::Error> for ConvertError {
+    fn from(err: io::Error) -> Self {
+        ConvertError::Io(err)
+    }
+}
+
+impl From<MmlError> for ConvertError {
+    fn from(err: MmlError) -> Self {
+        ConvertError::Verify(err)
+    }
+}
@@ -0,0 +1,244 @@
+use std::fmt;
+
+use super::dialect::MmlDialect;
+use super::ConvertOptions;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Length {
+    pub denominator: u32,
+    pub dots: u8,
+}
+
+impl Length {
+    pub fn new(denominator: u32) -> Self {
+        Length {
+            denominator,
+            dots: 0,
+        }
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.denominator)?;
+        for _ in 0..self.dots {
+            write!(f, ".")?;
+        }
+        Ok(())
+    }
+}
+
+struct Step {
+    length: Length,
+    ticks: u32,
+    exact: bool,
+}
+
+pub struct LengthTable {
+    steps: Vec<Step>,
+    max_dots: u8,
+}
+
+impl LengthTable {
+    pub fn new(ticks_per_beat: u32, options: &ConvertOptions, dialect: &dyn MmlDialect) -> Self {
+        let mut steps: Vec<Step> = Vec::new();
+        let mut denominator = 1;
+        while denominator <= options.min_length {
+            // Tuplets whose lengths lie between this length and half of it are tried
+            // before it, but only when they divide the remaining ticks exactly. N notes
+            // take the time of the largest power of two below N, so quintuplets of
+            // 16ths are written as 20ths.
+            let mut tuplets: Vec<u32> = options
+                .tuplets
+                .iter()
+                .filter_map(|notes| {
+                    let base = 1 << notes.ilog2();
+                    (denominator % base == 0).then(|| denominator * notes / base)
+                })
+                .filter(|tuplet| {
+                    (ticks_per_beat * 4).is_multiple_of(*tuplet) && dialect.supports_length(*tuplet)
+                })
+                .collect();
+            tuplets.sort();
+            tuplets.dedup();
+            for tuplet in tuplets {
+                steps.push(Step {
+                    length: Length::new(tuplet),
+                    ticks: ticks_per_beat * 4 / tuplet,
+                    exact: true,
+                });
+            }
+            steps.push(Step {
+                length: Length::new(denominator),
+                ticks: ticks_per_beat * 4 / denominator,
+                exact: false,
+            });
+            denominator *= 2;
+        }
+        steps.retain(|step| step.ticks > 0);
+
+        LengthTable {
+            steps,
+            max_dots: options.max_dots,
+        }
+    }
+
+    pub fn next(&self, ticks: u32) -> Option<(Length, u32)> {
+        self.steps
+            .iter()
+            .find(|step| {
+                if step.exact {
+                    ticks.is_multiple_of(step.ticks)
+                } else {
+                    ticks >= step.ticks
+                }
+            })
+            .map(|step| (step.length, step.ticks))
+    }
+
+    pub fn split(&self, mut ticks: u32) -> Vec<(Length, u32)> {
+        let mut lengths: Vec<(Length, u32)> = Vec::new();
+        while ticks > 0 {
+            match self.next(ticks) {
+                Some((length, length_ticks)) => {
+                    lengths.push((length, length_ticks));
+                    ticks -= length_ticks;
+                }
+                None => break,
+            }
+        }
+        self.join_dots(lengths)
+    }
+
+    // The ladder emits lengths in descending order, so a dotted length shows up as
+    // a run of halving lengths such as 4, 8, 16.
+    fn join_dots(&self, lengths: Vec<(Length, u32)>) -> Vec<(Length, u32)> {
+        let mut joined: Vec<(Length, u32)> = Vec::new();
+        for (length, ticks) in lengths {
+            if let Some((last, last_ticks)) = joined.last_mut() {
+                if last.dots < self.max_dots
+                    && length.dots == 0
+                    && length.denominator == last.denominator << (last.dots + 1)
+                {
+                    last.dots += 1;
+                    *last_ticks += ticks;
+                    continue;
+                }
+            }
+            joined.push((length, ticks));
+        }
+        joined
+    }
+
+    pub fn expresses(&self, ticks: u32) -> bool {
+        self.remainder(ticks) == 0
+    }
+
+    pub fn shortest(&self) -> u32 {
+        self.steps.iter().map(|step| step.ticks).min().unwrap_or(1)
+    }
+
+    pub fn remainder(&self, mut ticks: u32) -> u32 {
+        while ticks > 0 {
+            match self.next(ticks) {
+                Some((_, length_ticks)) => ticks -= length_ticks,
+                None => return ticks,
+            }
+        }
+        ticks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::dialect::{Mxdrv, Rp2040};
+
+    const TICKS_PER_BEAT: u32 = 480;
+
+    fn table(tuplets: &[u32], dialect: &dyn MmlDialect) -> LengthTable {
+        let options = ConvertOptions {
+            tuplets: tuplets.to_vec(),
+            ..ConvertOptions::default()
+        };
+        LengthTable::new(TICKS_PER_BEAT, &options, dialect)
+    }
+
+    fn lengths(table: &LengthTable, ticks: u32) -> Vec<String> {
+        let lengths = table.split(ticks);
+        assert_eq!(
+            lengths.iter().map(|(_, ticks)| ticks).sum::<u32>(),
+            ticks,
+            "{} ticks",
+            ticks
+        );
+        lengths
+            .iter()
+            .map(|(length, _)| length.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn straight_and_dotted() {
+        let table = table(&[3], &Rp2040);
+        let cases: [(u32, &[&str]); 7] = [
+            (1920, &["1"]),
+            (480, &["4"]),
+            (720, &["4."]),
+            (840, &["4.."]),
+            (900, &["4..", "32"]),
+            (1440, &["2."]),
+            (2400, &["1", "4"]),
+        ];
+        for (ticks, expected) in cases {
+            assert_eq!(lengths(&table, ticks), expected, "{} ticks", ticks);
+        }
+    }
+
+    #[test]
+    fn triplets() {
+        let table = table(&[3], &Rp2040);
+        let cases: [(u32, &[&str]); 6] = [
+            (640, &["3"]),
+            (320, &["6"]),
+            (160, &["12"]),
+            (80, &["24"]),
+            (800, &["4", "6"]),
+            (1280, &["3", "3"]),
+        ];
+        for (ticks, expected) in cases {
+            assert_eq!(lengths(&table, ticks), expected, "{} ticks", ticks);
+        }
+    }
+
+    #[test]
+    fn quintuplets() {
+        let table = table(&[3, 5], &Rp2040);
+        let cases: [(u32, &[&str]); 5] = [
+            (384, &["5"]),
+            (192, &["10"]),
+            (96, &["20"]),
+            (576, &["4", "20"]),
+            (160, &["12"]),
+        ];
+        for (ticks, expected) in cases {
+            assert_eq!(lengths(&table, ticks), expected, "{} ticks", ticks);
+        }
+        assert!(!self::table(&[3], &Rp2040).expresses(96));
+    }
+
+    #[test]
+    fn dialect_lengths() {
+        // 192 clocks per whole note leave out 5ths, 10ths and 20ths.
+        let table = table(&[3, 5], &Mxdrv);
+        assert_eq!(lengths(&table, 320), ["6"]);
+        assert!(!table.expresses(96));
+        assert!(!table.expresses(384));
+    }
+
+    #[test]
+    fn shortest() {
+        assert_eq!(table(&[3], &Rp2040).shortest(), 10);
+        assert_eq!(table(&[], &Rp2040).shortest(), 15);
+    }
+}
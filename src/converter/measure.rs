@@ -0,0 +1,68 @@
+use super::TimeSignatureEvent;
+
+struct MeasureSegment {
+    tick: u32,
+    bar: u32,
+    measure_ticks: u32,
+}
+
+pub struct MeasureMap {
+    segments: Vec<MeasureSegment>,
+}
+
+impl MeasureMap {
+    pub fn new(ticks_per_beat: u32, time_signature_events: &[TimeSignatureEvent]) -> Self {
+        let measure_ticks = |event: &TimeSignatureEvent| {
+            (ticks_per_beat * 4 * event.numerator as u32 / event.denominator as u32).max(1)
+        };
+        let mut segments: Vec<MeasureSegment> = vec![MeasureSegment {
+            tick: 0,
+            bar: 1,
+            measure_ticks: ticks_per_beat * 4,
+        }];
+        for event in time_signature_events.iter() {
+            let last = segments.last_mut().unwrap();
+            if event.tick == last.tick {
+                last.measure_ticks = measure_ticks(event);
+                continue;
+            }
+            // A signature change in the middle of a measure starts a new one.
+            let bars = (event.tick - last.tick).div_ceil(last.measure_ticks);
+            let bar = last.bar + bars;
+            segments.push(MeasureSegment {
+                tick: event.tick,
+                bar,
+                measure_ticks: measure_ticks(event),
+            });
+        }
+        MeasureMap { segments }
+    }
+
+    pub fn bar_at(&self, tick: u32) -> u32 {
+        let segment = self.segment_at(tick);
+        segment.bar + (tick - segment.tick) / segment.measure_ticks
+    }
+
+    pub fn next_bar_tick(&self, tick: u32) -> u32 {
+        let index = self
+            .segments
+            .iter()
+            .rposition(|segment| segment.tick <= tick)
+            .unwrap_or(0);
+        let segment = &self.segments[index];
+        let next = segment.tick
+            + ((tick - segment.tick) / segment.measure_ticks + 1) * segment.measure_ticks;
+        match self.segments.get(index + 1) {
+            Some(next_segment) if next_segment.tick < next => next_segment.tick,
+            _ => next,
+        }
+    }
+
+    fn segment_at(&self, tick: u32) -> &MeasureSegment {
+        self.segments
+            .iter()
+            .rev()
+            .find(|segment| segment.tick <= tick)
+            .unwrap_or(&self.segments[0])
+    }
+}
@@ -0,0 +1,57 @@
+use std::io::{self, Write};
+
+use super::dialect::MmlDialect;
+use super::length::Length;
+use super::OctaveDirection;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Token {
+    Note { key: u8, lengths: Vec<Length> },
+    Tie,
+    Rest(Length),
+    Tempo(f64),
+    Volume(u8),
+    Octave(u8),
+    OctaveUp,
+    OctaveDown,
+    Timbre(u8),
+    DefaultLength(Length),
+    BarLine(u32),
+}
+
+pub fn render(
+    tokens: &[Token],
+    bar_comments: bool,
+    octave_direction: OctaveDirection,
+    dialect: &dyn MmlDialect,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let mut default: Option<Length> = None;
+    let mut tied = false;
+    for token in tokens.iter() {
+        match token {
+            Token::Note { key, lengths } if tied => {
+                tied = false;
+                dialect.write_tied_note(out, *key, lengths, default)?;
+            }
+            Token::Note { key, lengths } => dialect.write_note(out, *key, lengths, default)?,
+            Token::Tie => {
+                tied = true;
+                dialect.write_tie(out)?;
+            }
+            Token::Rest(length) => dialect.write_rest(out, length, default)?,
+            Token::Tempo(tempo) => dialect.write_tempo(out, *tempo)?,
+            Token::Volume(volume) => dialect.write_volume(out, *volume)?,
+            Token::Octave(octave) => dialect.write_octave(out, *octave)?,
+            Token::OctaveUp => dialect.write_octave_step(out, true, octave_direction)?,
+            Token::OctaveDown => dialect.write_octave_step(out, false, octave_direction)?,
+            Token::Timbre(timbre) => dialect.write_timbre(out, *timbre)?,
+            Token::DefaultLength(length) => {
+                default = Some(*length);
+                dialect.write_default_length(out, length)?;
+            }
+            Token::BarLine(bar) => dialect.write_bar_line(out, *bar, bar_comments)?,
+        }
+    }
+    Ok(())
+}
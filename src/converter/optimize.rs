@@ -0,0 +1,142 @@
+use super::length::Length;
+use super::mml::Token;
+use super::OctaveMode;
+
+fn length_cost(length: &Length, default: Option<Length>) -> usize {
+    if default == Some(*length) {
+        0
+    } else {
+        length.to_string().len()
+    }
+}
+
+fn switch_cost(length: Option<Length>) -> usize {
+    match length {
+        Some(length) => 1 + length.to_string().len(),
+        None => 0,
+    }
+}
+
+fn lengths(tokens: &[Token]) -> impl Iterator<Item = &Length> {
+    tokens.iter().flat_map(|token| match token {
+        Token::Note { lengths, .. } => lengths.iter().collect::<Vec<&Length>>(),
+        Token::Rest(length) => vec![length],
+        _ => Vec::new(),
+    })
+}
+
+// Chooses a default length for every measure line so that the total number of
+// characters spent on lengths and `l` commands is minimal, then inserts the `l`
+// commands at the start of the lines where the default changes.
+pub fn default_length(tokens: &mut Vec<Token>) {
+    let lines: Vec<&[Token]> = tokens
+        .split_inclusive(|token| matches!(token, Token::BarLine(_)))
+        .collect();
+
+    let mut states: Vec<Option<Length>> = vec![None];
+    for length in lengths(tokens) {
+        if length.dots == 0 && !states.contains(&Some(*length)) {
+            states.push(Some(*length));
+        }
+    }
+    if states.len() == 1 {
+        return;
+    }
+
+    let mut costs: Vec<usize> = vec![0; states.len()];
+    for (index, state) in states.iter().enumerate() {
+        costs[index] = switch_cost(*state);
+    }
+    let mut choices: Vec<Vec<usize>> = Vec::new();
+    for (line_index, line) in lines.iter().enumerate() {
+        let mut next_costs: Vec<usize> = vec![0; states.len()];
+        let mut line_choices: Vec<usize> = vec![0; states.len()];
+        for (index, state) in states.iter().enumerate() {
+            let line_cost: usize = lengths(line)
+                .map(|length| length_cost(length, *state))
+                .sum();
+            let (previous, cost) = if line_index == 0 {
+                (index, costs[index])
+            } else {
+                (0..states.len())
+                    .map(|previous| {
+                        let switch = if previous == index || state.is_none() {
+                            0
+                        } else {
+                            switch_cost(*state)
+                        };
+                        (previous, costs[previous] + switch)
+                    })
+                    .min_by_key(|(_, cost)| *cost)
+                    .unwrap()
+            };
+            next_costs[index] = cost + line_cost;
+            line_choices[index] = previous;
+        }
+        costs = next_costs;
+        choices.push(line_choices);
+    }
+
+    let mut state = (0..states.len()).min_by_key(|index| costs[*index]).unwrap();
+    let mut line_states: Vec<usize> = vec![0; lines.len()];
+    for line_index in (0..lines.len()).rev() {
+        line_states[line_index] = state;
+        state = choices[line_index][state];
+    }
+
+    let mut optimized: Vec<Token> = Vec::new();
+    let mut current: Option<Length> = None;
+    for (line, state) in lines.iter().zip(line_states.iter()) {
+        let state = states[*state];
+        if let Some(length) = state {
+            if state != current {
+                optimized.push(Token::DefaultLength(length));
+                current = state;
+            }
+        }
+        optimized.extend_from_slice(line);
+    }
+    *tokens = optimized;
+}
+
+// Replaces absolute `o` commands with `<`/`>` steps. `Auto` only does so when the
+// jump is at most `max_steps` octaves, `Relative` always does. The first `o` stays
+// absolute when the initial octave is unknown.
+pub fn relative_octave(
+    tokens: &mut Vec<Token>,
+    mode: OctaveMode,
+    max_steps: u8,
+    initial: Option<u8>,
+) {
+    if mode == OctaveMode::Absolute {
+        return;
+    }
+
+    let mut optimized: Vec<Token> = Vec::new();
+    let mut octave = initial;
+    for token in tokens.drain(..) {
+        if let (Token::Octave(next), Some(current)) = (&token, octave) {
+            let next = *next;
+            let steps = next.abs_diff(current);
+            if mode == OctaveMode::Relative || steps <= max_steps {
+                let step = if next > current {
+                    Token::OctaveUp
+                } else {
+                    Token::OctaveDown
+                };
+                for _ in 0..steps {
+                    optimized.push(step.clone());
+                }
+            } else {
+                optimized.push(token);
+            }
+            octave = Some(next);
+        } else {
+            if let Token::Octave(next) = token {
+                octave = Some(next);
+            }
+            optimized.push(token);
+        }
+    }
+    *tokens = optimized;
+}
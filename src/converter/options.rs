@@ -0,0 +1,87 @@
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OctaveMode {
+    Absolute,
+    Relative,
+    Auto,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rounding {
+    Floor,
+    Nearest,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OctaveDirection {
+    GreaterUp,
+    LessUp,
+}
+
+#[derive(Clone, Debug)]
+pub struct ConvertOptions {
+    pub tracks: Option<Vec<usize>>,
+    pub channels: Option<Vec<u8>>,
+    pub min_length: u32,
+    // Tuplets written with their own lengths, 3 for triplets, 5 for quintuplets.
+    pub tuplets: Vec<u32>,
+    pub max_dots: u8,
+    pub grid: Option<u32>,
+    pub rounding: Rounding,
+    pub swing: bool,
+    pub timecode_tempo: Option<u32>,
+    pub bar_comments: bool,
+    pub default_length: bool,
+    pub octave_mode: OctaveMode,
+    pub octave_steps: u8,
+    pub octave_direction: Option<OctaveDirection>,
+    pub title: Option<String>,
+    pub composer: Option<String>,
+    pub verify: bool,
+}
+
+const TUPLET_MAX: u32 = 31;
+
+impl ConvertOptions {
+    // N notes in the time of the largest power of two below N.
+    pub fn tuplet_supported(notes: u32) -> bool {
+        (3..=TUPLET_MAX).contains(&notes) && !notes.is_power_of_two()
+    }
+
+    pub fn track_selected(&self, track: usize) -> bool {
+        match &self.tracks {
+            Some(tracks) => tracks.contains(&track),
+            None => true,
+        }
+    }
+
+    pub fn channel_selected(&self, channel: u8) -> bool {
+        match &self.channels {
+            Some(channels) => channels.contains(&channel),
+            None => true,
+        }
+    }
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        ConvertOptions {
+            tracks: None,
+            channels: None,
+            min_length: 128,
+            tuplets: vec![3],
+            max_dots: 2,
+            grid: None,
+            rounding: Rounding::Floor,
+            swing: false,
+            timecode_tempo: None,
+            bar_comments: false,
+            default_length: true,
+            octave_mode: OctaveMode::Auto,
+            octave_steps: 1,
+            octave_direction: None,
+            title: None,
+            composer: None,
+            verify: false,
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use super::length::LengthTable;
+use super::verify::SourceNote;
+use super::{ConvertError, ConvertOptions, Rounding};
+
+const SWING_MIN_NOTES: usize = 4;
+
+pub struct Quantizer<'a> {
+    length_table: &'a LengthTable,
+    ticks_per_beat: u32,
+    grid: Option<u32>,
+    rounding: Rounding,
+    swing: Option<u32>,
+}
+
+impl<'a> Quantizer<'a> {
+    pub fn new(
+        length_table: &'a LengthTable,
+        ticks_per_beat: u32,
+        options: &ConvertOptions,
+    ) -> Result<Self, ConvertError> {
+        let grid = match options.grid {
+            Some(grid) => {
+                let ticks = ticks_per_beat * 4 / grid.max(1);
+                if ticks == 0 || !length_table.expresses(ticks) {
+                    return Err(ConvertError::GridUnsupported { grid });
+                }
+                Some(ticks)
+            }
+            None => None,
+        };
+        Ok(Quantizer {
+            length_table,
+            ticks_per_beat,
+            grid,
+            rounding: options.rounding,
+            swing: None,
+        })
+    }
+
+    // Off-beats are onsets between 0.45 and 0.85 of a beat. When two thirds of them sit
+    // within 1/20 of a beat of the triplet position, they are kept there instead of
+    // being pulled to the straight grid.
+    pub fn detect_swing(&mut self, notes: &[SourceNote]) -> bool {
+        let beat = self.ticks_per_beat;
+        if !beat.is_multiple_of(3) {
+            return false;
+        }
+        let off_beats: Vec<u32> = notes
+            .iter()
+            .map(|note| note.tick % beat)
+            .filter(|phase| phase * 20 > beat * 9 && phase * 20 < beat * 17)
+            .collect();
+        let swung = off_beats
+            .iter()
+            .filter(|phase| phase.abs_diff(beat * 2 / 3) * 20 <= beat)
+            .count();
+        if off_beats.len() >= SWING_MIN_NOTES && swung * 3 >= off_beats.len() * 2 {
+            self.swing = Some(beat * 2 / 3);
+        }
+        self.swing.is_some()
+    }
+
+    // Length of the shortest note, notes never collapse below it.
+    pub fn shortest(&self) -> u32 {
+        self.grid.unwrap_or(self.length_table.shortest())
+    }
+
+    pub fn aligned(&self, tick: u32, pre_tick: u32) -> bool {
+        tick >= pre_tick && self.length_table.expresses(tick - pre_tick)
+    }
+
+    // Moves `tick` onto the grid, never before `lower`. The result can always be
+    // written as lengths counted from `pre_tick`, the previous event of the part.
+    pub fn quantize(&self, tick: u32, lower: u32, pre_tick: u32) -> u32 {
+        if let Some(swing) = self.swing {
+            let swing = tick / self.ticks_per_beat * self.ticks_per_beat + swing;
+            if swing >= lower
+                && tick.abs_diff(swing) * 12 <= self.ticks_per_beat
+                && self.aligned(swing, pre_tick)
+            {
+                return swing;
+            }
+        }
+        if tick <= lower {
+            return lower;
+        }
+        let floor = |tick: u32| {
+            if tick <= lower {
+                lower
+            } else {
+                (tick - self.length_table.remainder(tick - pre_tick)).max(lower)
+            }
+        };
+        let (down, up) = match self.grid {
+            Some(grid) => (floor(tick / grid * grid), tick / grid * grid + grid),
+            None => (floor(tick), floor(tick) + self.length_table.shortest()),
+        };
+        match self.rounding {
+            Rounding::Nearest
+                if up > tick && up - tick < tick - down && self.aligned(up, pre_tick) =>
+            {
+                up
+            }
+            _ => down,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::dialect::Rp2040;
+
+    const TICKS_PER_BEAT: u32 = 480;
+
+    fn options(grid: Option<u32>, rounding: Rounding) -> ConvertOptions {
+        ConvertOptions {
+            grid,
+            rounding,
+            ..ConvertOptions::default()
+        }
+    }
+
+    fn table(options: &ConvertOptions) -> LengthTable {
+        LengthTable::new(TICKS_PER_BEAT, options, &Rp2040)
+    }
+
+    fn note(tick: u32) -> SourceNote {
+        SourceNote {
+            track: 0,
+            channel: 0,
+            key: 60,
+            tick,
+            ticks: 120,
+        }
+    }
+
+    #[test]
+    fn grid() {
+        let options = options(Some(16), Rounding::Floor);
+        let table = table(&options);
+        let quantizer = Quantizer::new(&table, TICKS_PER_BEAT, &options).unwrap();
+        assert_eq!(quantizer.shortest(), 120);
+        for (tick, expected) in [(0, 0), (119, 0), (120, 120), (230, 120), (1000, 960)] {
+            assert_eq!(quantizer.quantize(tick, 0, 0), expected, "tick {}", tick);
+        }
+        // The grid is absolute, the previous event may lie off it.
+        assert_eq!(quantizer.quantize(500, 0, 20), 480);
+    }
+
+    #[test]
+    fn floor_and_nearest() {
+        let floor = options(Some(16), Rounding::Floor);
+        let nearest = options(Some(16), Rounding::Nearest);
+        let (floor_table, nearest_table) = (table(&floor), table(&nearest));
+        let floor = Quantizer::new(&floor_table, TICKS_PER_BEAT, &floor).unwrap();
+        let nearest = Quantizer::new(&nearest_table, TICKS_PER_BEAT, &nearest).unwrap();
+        for (tick, down, up) in [(170, 120, 120), (190, 120, 240), (470, 360, 480)] {
+            assert_eq!(floor.quantize(tick, 0, 0), down, "tick {}", tick);
+            assert_eq!(nearest.quantize(tick, 0, 0), up, "tick {}", tick);
+        }
+    }
+
+    #[test]
+    fn nearest_without_grid() {
+        let options = ConvertOptions {
+            min_length: 16,
+            tuplets: Vec::new(),
+            rounding: Rounding::Nearest,
+            ..ConvertOptions::default()
+        };
+        let table = table(&options);
+        let quantizer = Quantizer::new(&table, TICKS_PER_BEAT, &options).unwrap();
+        assert_eq!(quantizer.quantize(100, 0, 0), 120);
+        assert_eq!(quantizer.quantize(20, 0, 0), 0);
+        assert_eq!(quantizer.quantize(470, 0, 0), 480);
+    }
+
+    #[test]
+    fn never_before_lower() {
+        // A NoteOff is never moved onto its NoteOn, notes keep at least one step.
+        for rounding in [Rounding::Floor, Rounding::Nearest] {
+            let options = options(Some(16), rounding);
+            let table = table(&options);
+            let quantizer = Quantizer::new(&table, TICKS_PER_BEAT, &options).unwrap();
+            let note_on = 480;
+            for tick in [note_on, note_on + 1, note_on + 60, note_on + 119] {
+                let note_off = quantizer.quantize(tick, note_on + quantizer.shortest(), note_on);
+                assert_eq!(note_off, note_on + 120, "tick {}", tick);
+            }
+        }
+    }
+
+    #[test]
+    fn swing() {
+        let options = options(None, Rounding::Floor);
+        let table = table(&options);
+        let mut quantizer = Quantizer::new(&table, TICKS_PER_BEAT, &options).unwrap();
+        let swung: Vec<SourceNote> = (0..4)
+            .flat_map(|beat| [note(beat * 480), note(beat * 480 + 310 + beat * 5)])
+            .collect();
+        assert!(quantizer.detect_swing(&swung));
+        assert_eq!(quantizer.quantize(325, 0, 0), 320);
+        assert_eq!(quantizer.quantize(1290, 960, 960), 1280);
+        // Far from the triplet position, notes fall back to the length ladder.
+        assert_eq!(quantizer.quantize(240, 0, 0), 240);
+    }
+
+    #[test]
+    fn straight_is_not_swing() {
+        let options = options(None, Rounding::Floor);
+        let table = table(&options);
+        let mut quantizer = Quantizer::new(&table, TICKS_PER_BEAT, &options).unwrap();
+        let straight: Vec<SourceNote> = (0..8).map(|index| note(index * 240)).collect();
+        assert!(!quantizer.detect_swing(&straight));
+        // Without swing the onset is written exactly as 8+32+128+192.
+        assert_eq!(quantizer.quantize(325, 0, 0), 325);
+
+        // Too few off-beats.
+        let few: Vec<SourceNote> = (0..3).map(|beat| note(beat * 480 + 320)).collect();
+        assert!(!quantizer.detect_swing(&few));
+    }
+
+    #[test]
+    fn grid_unsupported() {
+        for grid in [5, 7, 256, 10000] {
+            let options = options(Some(grid), Rounding::Floor);
+            let table = table(&options);
+            assert!(
+                matches!(
+                    Quantizer::new(&table, TICKS_PER_BEAT, &options),
+                    Err(ConvertError::GridUnsupported { grid: error_grid }) if error_grid == grid
+                ),
+                "grid {}",
+                grid
+            );
+        }
+        let options = options(Some(12), Rounding::Floor);
+        let table = table(&options);
+        assert!(Quantizer::new(&table, TICKS_PER_BEAT, &options).is_ok());
+    }
+}
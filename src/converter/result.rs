@@ -0,0 +1,75 @@
+use std::fmt;
+use std::io;
+
+use super::mml::Token;
+use super::{Diagnostic, Severity, Verification};
+
+#[derive(Clone, Debug)]
+pub struct MmlPart {
+    pub track: usize,
+    pub channel: u8,
+    pub sub_channel: u8,
+    pub label: Option<String>,
+    pub tokens: Vec<Token>,
+    pub mml: String,
+}
+
+impl MmlPart {
+    pub fn new(track: usize, channel: u8, sub_channel: u8, tokens: Vec<Token>) -> Self {
+        MmlPart {
+            track,
+            channel,
+            sub_channel,
+            label: None,
+            tokens,
+            mml: String::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TrackInfo {
+    pub index: usize,
+    pub event_count: usize,
+    pub channels: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ConversionResult {
+    pub ticks_per_beat: u32,
+    pub tracks: Vec<TrackInfo>,
+    pub dialect: String,
+    pub title: Option<String>,
+    pub composer: Option<String>,
+    pub parts: Vec<MmlPart>,
+    pub diagnostics: Vec<Diagnostic>,
+    pub document: String,
+    pub verification: Option<Verification>,
+}
+
+impl ConversionResult {
+    pub fn track_count(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn write_to<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "{}", self)?;
+        out.flush()
+    }
+
+    pub fn has_warnings(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Warning)
+    }
+
+    pub fn parts_for_track(&self, track: usize) -> impl Iterator<Item = &MmlPart> {
+        self.parts.iter().filter(move |part| part.track == track)
+    }
+}
+
+impl fmt::Display for ConversionResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.document)
+    }
+}
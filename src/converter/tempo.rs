@@ -0,0 +1,181 @@
+use super::measure::MeasureMap;
+use super::verify::SourceNote;
+use super::{Diagnostic, DiagnosticKind, Severity, TempoEvent};
+
+// Tempos the dialect cannot write exactly alternate between the nearest values it can
+// write. The value of every measure is chosen so that the time error carried over from
+// the previous measures stays as small as possible, so long songs do not drift.
+pub fn compensate(
+    events: &mut Vec<TempoEvent>,
+    ticks_per_beat: u32,
+    decimals: u8,
+    end_tick: u32,
+    measure_map: &MeasureMap,
+    notes: &[SourceNote],
+) -> Vec<Diagnostic> {
+    let scale = 10f64.powi(decimals as i32);
+    let seconds = |ticks: u32, tempo: f64| ticks as f64 * 60.0 / (tempo * ticks_per_beat as f64);
+    // Tempo changes go where no note is held, so they do not split notes.
+    let held = |tick: u32| {
+        notes
+            .iter()
+            .any(|note| note.tick < tick && tick < note.tick + note.ticks)
+    };
+
+    events.sort_by_key(|event| event.tick);
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut compensated: Vec<TempoEvent> = Vec::new();
+    let mut error = 0.0;
+    for (index, event) in events.iter().enumerate() {
+        let end = events
+            .get(index + 1)
+            .map_or(end_tick, |next| next.tick)
+            .max(event.tick);
+        let exact = event.beats_per_minute();
+        let low = ((exact * scale).floor() / scale).max(1.0 / scale);
+        let high = ((exact * scale).ceil() / scale).max(1.0 / scale);
+
+        let mut tick = event.tick;
+        let mut used = (false, false);
+        loop {
+            let mut next = end;
+            if low != high {
+                next = measure_map.next_bar_tick(tick);
+                while next < end && held(next) {
+                    next = measure_map.next_bar_tick(next);
+                }
+                next = next.min(end);
+            }
+            let ticks = next - tick;
+            let target = error + seconds(ticks, exact);
+            let tempo =
+                if (target - seconds(ticks, low)).abs() <= (target - seconds(ticks, high)).abs() {
+                    used.0 = true;
+                    low
+                } else {
+                    used.1 = true;
+                    high
+                };
+            error = target - seconds(ticks, tempo);
+            compensated.push(TempoEvent {
+                tick,
+                tempo,
+                ..*event
+            });
+            tick = next;
+            if tick >= end {
+                break;
+            }
+        }
+
+        if used.0 && used.1 {
+            diagnostics.push(
+                Diagnostic::song(Severity::Info, DiagnosticKind::TempoAlternated, event.tick)
+                    .with_message(format!(
+                        "Tempo {:.3} alternates between {} and {}.",
+                        exact, low, high
+                    )),
+            );
+        }
+    }
+    *events = compensated;
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICKS_PER_BEAT: u32 = 480;
+    const MEASURE: u32 = TICKS_PER_BEAT * 4;
+    const MEASURES: u32 = 64;
+
+    fn seconds(ticks: u32, tempo: f64) -> f64 {
+        ticks as f64 * 60.0 / (tempo * TICKS_PER_BEAT as f64)
+    }
+
+    // Largest difference between the written and the exact time at any tempo change
+    // or at the end, together with the tempos used.
+    fn drift(microseconds_per_beat: u32, decimals: u8, notes: &[SourceNote]) -> (f64, Vec<f64>) {
+        let end_tick = MEASURE * MEASURES;
+        let mut events = vec![TempoEvent::new(0, microseconds_per_beat)];
+        let exact = events[0].beats_per_minute();
+        let measure_map = MeasureMap::new(TICKS_PER_BEAT, &[]);
+        compensate(
+            &mut events,
+            TICKS_PER_BEAT,
+            decimals,
+            end_tick,
+            &measure_map,
+            notes,
+        );
+
+        let mut written = 0.0;
+        let mut max_drift: f64 = 0.0;
+        for (index, event) in events.iter().enumerate() {
+            let end = events.get(index + 1).map_or(end_tick, |next| next.tick);
+            written += seconds(end - event.tick, event.tempo);
+            max_drift = max_drift.max((written - seconds(end, exact)).abs());
+        }
+        let mut tempos: Vec<f64> = events.iter().map(|event| event.tempo).collect();
+        tempos.sort_by(f64::total_cmp);
+        tempos.dedup();
+        (max_drift, tempos)
+    }
+
+    // Time one measure gains or loses when its tempo moves by one written unit.
+    fn unit(tempo: f64, decimals: u8) -> f64 {
+        let step = 10f64.powi(-(decimals as i32));
+        seconds(MEASURE, tempo) - seconds(MEASURE, tempo + step)
+    }
+
+    #[test]
+    fn integer_tempo() {
+        // 128.4 BPM written as 128 would be 0.2 seconds late after 64 measures.
+        let (drift, tempos) = drift(467_290, 0, &[]);
+        assert_eq!(tempos, vec![128.0, 129.0]);
+        assert!(drift <= unit(128.0, 0), "drift {}", drift);
+        assert!(seconds(MEASURE * MEASURES, 128.0) - seconds(MEASURE * MEASURES, 128.4) > 0.1);
+    }
+
+    #[test]
+    fn decimal_tempo() {
+        // 137.2997... BPM with two decimals.
+        let (drift, tempos) = drift(437_000, 2, &[]);
+        assert_eq!(tempos, vec![137.29, 137.3]);
+        assert!(drift <= unit(137.29, 2), "drift {}", drift);
+    }
+
+    #[test]
+    fn exact_tempo() {
+        let (drift, tempos) = drift(500_000, 0, &[]);
+        assert_eq!(tempos, vec![120.0]);
+        assert!(drift < 1e-9);
+    }
+
+    #[test]
+    fn held_notes() {
+        // A note held over every bar line leaves no place to alternate.
+        let notes: Vec<SourceNote> = (0..MEASURES)
+            .map(|measure| SourceNote {
+                track: 0,
+                channel: 0,
+                key: 60,
+                tick: measure * MEASURE + MEASURE / 2,
+                ticks: MEASURE,
+            })
+            .collect();
+        let mut events = vec![TempoEvent::new(0, 467_290)];
+        let measure_map = MeasureMap::new(TICKS_PER_BEAT, &[]);
+        let diagnostics = compensate(
+            &mut events,
+            TICKS_PER_BEAT,
+            0,
+            MEASURE * MEASURES,
+            &measure_map,
+            &notes,
+        );
+        assert_eq!(events.len(), 1);
+        assert!(diagnostics.is_empty());
+    }
+}
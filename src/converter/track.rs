@@ -1,6 +1,16 @@
+use super::dialect::MmlDialect;
 use super::event::Event;
 use super::key_data::KeyData;
+use super::length::LengthTable;
+use super::measure::MeasureMap;
+use super::mml::Token;
+use super::optimize;
+use super::quantize::Quantizer;
+use super::ConvertError;
+use super::ConvertOptions;
+use super::MmlPart;
 use super::TempoEvent;
+use super::{Diagnostic, DiagnosticKind};
 
 mod note_data;
 use note_data::NoteData;
@@ -10,13 +20,14 @@ pub enum DataKind {
     NoteOn(KeyData),
     NoteOff(KeyData),
     ChangeTimbre(u8),
-    ChangeTempo(u32),
+    ChangeTempo(f64),
 }
 
 pub struct Track {
     pub track_index: usize,
     pub events: Vec<Event>,
     pub channels: Vec<u8>,
+    pub diagnostics: Vec<Diagnostic>,
     channel_data_list: Vec<ChannelData>,
 }
 
@@ -34,28 +45,31 @@ impl ChannelData {
     }
 }
 
-struct MmlStatus {
-    ticks_per_beat: u32,
-    line_ticks: u32,
+struct MmlStatus<'a> {
+    measure_map: &'a MeasureMap,
     tick: u32,
+    next_bar_tick: u32,
+    tokens: Vec<Token>,
 }
 
-impl MmlStatus {
-    fn new(ticks_per_beat: u32) -> Self {
+impl<'a> MmlStatus<'a> {
+    fn new(measure_map: &'a MeasureMap) -> Self {
         MmlStatus {
-            ticks_per_beat,
-            line_ticks: 0,
+            measure_map,
             tick: 0,
+            next_bar_tick: measure_map.next_bar_tick(0),
+            tokens: Vec::new(),
         }
     }
 
     fn add_line_ticks(&mut self, ticks: u32) {
-        self.line_ticks += ticks;
         self.tick += ticks;
 
-        while self.line_ticks >= self.ticks_per_beat * 4 {
-            self.line_ticks -= self.ticks_per_beat * 4;
-            print!("\n");
+        while self.tick >= self.next_bar_tick {
+            self.tokens.push(Token::BarLine(
+                self.measure_map.bar_at(self.next_bar_tick - 1),
+            ));
+            self.next_bar_tick = self.measure_map.next_bar_tick(self.next_bar_tick);
         }
     }
 }
@@ -66,6 +80,7 @@ impl Track {
             track_index,
             events: Vec::new(),
             channels: Vec::new(),
+            diagnostics: Vec::new(),
             channel_data_list: Vec::new(),
         }
     }
@@ -77,75 +92,51 @@ impl Track {
         }
     }
 
-    fn get_new_channel(notes: &Vec<NoteData>) -> Option<u8> {
-        for index in 0..u8::max_value() {
-            if !notes.iter().any(|note| note.sub_channel == index) {
-                return Some(index);
-            }
-        }
-        None
+    fn warning(&mut self, kind: DiagnosticKind, event: &Event, sub_channel: Option<u8>) {
+        self.diagnostics.push(Self::event_warning(
+            self.track_index,
+            kind,
+            event,
+            sub_channel,
+        ));
     }
 
-    fn get_channel(notes: &Vec<NoteData>, key: &u8) -> Option<u8> {
-        for note in notes {
-            if note.key == *key {
-                return Some(note.sub_channel);
-            }
-        }
-        None
+    fn event_warning(
+        track_index: usize,
+        kind: DiagnosticKind,
+        event: &Event,
+        sub_channel: Option<u8>,
+    ) -> Diagnostic {
+        let key = match event.data_kind {
+            DataKind::NoteOn(key_data) | DataKind::NoteOff(key_data) => Some(key_data.key),
+            _ => None,
+        };
+        Diagnostic::warning(
+            kind,
+            track_index,
+            event.tick,
+            event.channel,
+            sub_channel,
+            key,
+        )
     }
 
-    fn divsion_tick(ticks_per_beat: u32, mut tick: u32) -> u32 {
-        while tick > 0 {
-            if tick >= ticks_per_beat * 4 {
-                // 1
-                tick -= ticks_per_beat * 4
-            } else if tick >= ticks_per_beat * 2 {
-                // 2
-                tick -= ticks_per_beat * 2
-            } else if tick >= ticks_per_beat {
-                // 4
-                tick -= ticks_per_beat
-            } else if tick % (ticks_per_beat / 3) == 0 {
-                // 4-3
-                tick -= ticks_per_beat / 3;
-            } else if tick >= ticks_per_beat / 2 {
-                // 8
-                tick -= ticks_per_beat / 2;
-            } else if tick % (ticks_per_beat / 6) == 0 {
-                // 8-3
-                tick -= ticks_per_beat / 6;
-            } else if tick >= ticks_per_beat / 4 {
-                // 16
-                tick -= ticks_per_beat / 4
-            } else if tick % (ticks_per_beat / 12) == 0 {
-                // 16-3
-                tick -= ticks_per_beat / 12;
-            } else if tick >= ticks_per_beat / 8 {
-                // 32
-                tick -= ticks_per_beat / 8
-            } else if tick % (ticks_per_beat / 24) == 0 {
-                // 32-3
-                tick -= ticks_per_beat / 24;
-            } else if tick >= ticks_per_beat / 16 {
-                // 64
-                tick -= ticks_per_beat / 16
-            } else if tick % (ticks_per_beat / 48) == 0 {
-                // 64-3
-                tick -= ticks_per_beat / 48;
-            } else if tick >= ticks_per_beat / 32 {
-                // 128
-                tick -= ticks_per_beat / 32
-            } else {
-                return tick;
-            }
-        }
-        tick
+    fn get_new_channel(notes: &[NoteData]) -> Option<u8> {
+        (0..u8::MAX).find(|&index| !notes.iter().any(|note| note.sub_channel == index))
     }
 
-    pub fn parse1(&mut self, tempo_events: &mut Vec<TempoEvent>) -> Result<(), String> {
-        println!("Track:{} Channel:{:?}", self.track_index, self.channels);
+    fn get_channel(notes: &[NoteData], key: &u8) -> Option<u8> {
+        notes
+            .iter()
+            .find(|note| note.key == *key)
+            .map(|note| note.sub_channel)
+    }
 
+    pub fn parse1(
+        &mut self,
+        tempo_events: &mut [TempoEvent],
+        tempo_in_tie: bool,
+    ) -> Result<(), ConvertError> {
         self.events.sort_by(|e1, e2| {
             let order = e1.tick.cmp(&e2.tick);
             if order == std::cmp::Ordering::Equal {
@@ -163,7 +154,7 @@ impl Track {
             }
         });
 
-        self.events.sort_by(|e1, e2| e1.channel.cmp(&e2.channel));
+        self.events.sort_by_key(|e| e.channel);
 
         let mut notes: Vec<NoteData> = Vec::new();
         let mut pre_channel: u8 = 0;
@@ -188,16 +179,20 @@ impl Track {
                     let sub_channel: u8;
                     match Track::get_channel(&notes, &key_data.key) {
                         Some(_) => {
-                            println!(
-                                "Warning! Tick:{} Channel:{} key:{} Continuous NoteOn is not supported.",
-                                self.events[index].tick,self.events[index].channel,key_data.key
-                            );
-                            note_on_events_delete.push(self.events[index])
+                            let event = self.events[index];
+                            self.warning(DiagnosticKind::ContinuousNoteOn, &event, None);
+                            note_on_events_delete.push(event)
                         }
                         None => {
                             match Track::get_new_channel(&notes) {
                                 Some(new_channel) => sub_channel = new_channel,
-                                None => return Err(format!("Failed to get subchannel.")),
+                                None => {
+                                    return Err(ConvertError::SubChannelExhausted {
+                                        track: self.track_index,
+                                        tick: self.events[index].tick,
+                                        channel: self.events[index].channel,
+                                    })
+                                }
                             }
 
                             if sub_channel > sub_channel_max {
@@ -206,11 +201,7 @@ impl Track {
 
                             self.events[index].sub_channel = sub_channel;
 
-                            notes.push(NoteData::new(
-                                key_data.key,
-                                self.events[index].tick,
-                                sub_channel,
-                            ));
+                            notes.push(NoteData::new(key_data.key, sub_channel));
 
                             if !valid_channels.contains(&(self.events[index].channel)) {
                                 valid_channels.push(self.events[index].channel);
@@ -238,11 +229,9 @@ impl Track {
                                 key_data.key
                             ))
                             */
-                            println!(
-                                "Warning! Tick:{} Channel:{} Key:{} No Key to NoteOff was found.",
-                                self.events[index].tick, self.events[index].channel, key_data.key
-                            );
-                            note_off_events_delete.push(self.events[index]);
+                            let event = self.events[index];
+                            self.warning(DiagnosticKind::NoteOffWithoutNoteOn, &event, None);
+                            note_off_events_delete.push(event);
                         }
                     }
                     notes.retain(|e1| e1.key != key_data.key);
@@ -280,28 +269,21 @@ impl Track {
             self.channel_data_list
                 .push(ChannelData::new(pre_channel, sub_channel_max));
         }
-        for channel_data in self.channel_data_list.iter() {
-            println!(
-                "Channel:{} SubChannels:{}",
-                channel_data.channel, channel_data.sub_channel_max
-            );
-        }
-
         for change_timbre_event in change_timbre_events.iter() {
             for channel_data in self.channel_data_list.iter() {
-                if channel_data.channel == change_timbre_event.channel {
-                    if channel_data.sub_channel_max > 0 {
-                        for index in 1..channel_data.sub_channel_max + 1 {
-                            self.events.insert(
-                                0,
-                                Event::new_with_sub(
-                                    change_timbre_event.channel,
-                                    index,
-                                    change_timbre_event.tick,
-                                    change_timbre_event.data_kind,
-                                ),
-                            );
-                        }
+                if channel_data.channel == change_timbre_event.channel
+                    && channel_data.sub_channel_max > 0
+                {
+                    for index in 1..channel_data.sub_channel_max + 1 {
+                        self.events.insert(
+                            0,
+                            Event::new_with_sub(
+                                change_timbre_event.channel,
+                                index,
+                                change_timbre_event.tick,
+                                change_timbre_event.data_kind,
+                            ),
+                        );
                     }
                 }
             }
@@ -309,7 +291,7 @@ impl Track {
 
         for channel_data in self.channel_data_list.iter() {
             for tempo_event in tempo_events.iter() {
-                if tempo_event.enable == true {
+                if tempo_event.enable {
                     for index in 0..channel_data.sub_channel_max + 1 {
                         self.events.insert(
                             0,
@@ -325,22 +307,24 @@ impl Track {
             }
         }
 
-        self.events.sort_by(|e1, e2| e1.tick.cmp(&e2.tick));
         self.events
-            .sort_by(|e1, e2| e1.sub_channel.cmp(&e2.sub_channel));
-        self.events.sort_by(|e1, e2| e1.channel.cmp(&e2.channel));
+            .sort_by_key(|e| !matches!(e.data_kind, DataKind::NoteOff(_)));
+        self.events.sort_by_key(|e| e.tick);
+        self.events.sort_by_key(|e| e.sub_channel);
+        self.events.sort_by_key(|e| e.channel);
 
         self.events
             .retain(|e1| valid_channels.contains(&e1.channel));
 
         let mut note_on = false;
-        let mut timbre_events_delete: Vec<Event> = Vec::new();
         for event in self.events.iter() {
             match event.data_kind {
                 DataKind::NoteOn(_) => note_on = true,
                 DataKind::NoteOff(_) => note_on = false,
                 DataKind::ChangeTempo(_) => {
-                    if note_on == true {
+                    // The held note is split into tied segments around the tempo
+                    // change when the dialect can write it, see `write_part`.
+                    if note_on && !tempo_in_tie {
                         for tempo_event in tempo_events.iter_mut() {
                             if tempo_event.tick == event.tick {
                                 tempo_event.enable = false;
@@ -351,74 +335,59 @@ impl Track {
                             "Tempo changes in the middle of a sound are not supported."
                         ));
                         */
-                        println!(
-                            "Warning! Tick:{} Channel:{} Sub:{} Kind:{:?} Tempo changes in the middle of a sound are not supported."
-                            ,event.tick,event.channel,event.sub_channel,event.data_kind
-                        );
-                    }
-                }
-                DataKind::ChangeTimbre(_) => {
-                    if note_on == true {
-                        timbre_events_delete.push(*event);
-                        println!(
-                            "Warning! Tick:{} Channel:{} Sub:{} Kind:{:?} Timbre changes in the middle of a sound are not supported."
-                            ,event.tick,event.channel,event.sub_channel,event.data_kind
-                        )
+                        self.diagnostics.push(Self::event_warning(
+                            self.track_index,
+                            DiagnosticKind::TempoChangeDuringNote,
+                            event,
+                            Some(event.sub_channel),
+                        ));
                     }
                 }
+                // Held program changes are deferred by `write_part`.
+                DataKind::ChangeTimbre(_) => {}
             }
         }
-        self.events.retain(|event| match event.data_kind {
-            DataKind::ChangeTempo(_) => false,
-            _ => true,
-        });
         self.events
-            .retain(|event| !timbre_events_delete.contains(event));
+            .retain(|event| !matches!(event.data_kind, DataKind::ChangeTempo(_)));
 
         if self.events.len() >= 2 {
             for index in 0..self.events.len() - 1 {
                 if let DataKind::NoteOn(key_data_on) = self.events[index].data_kind {
                     let mut result = false;
-                    if let DataKind::NoteOff(key_data_off) = self.events[index + 1].data_kind {
-                        if key_data_on.key == key_data_off.key
-                            && self.events[index].channel == self.events[index + 1].channel
-                            && self.events[index].sub_channel == self.events[index + 1].sub_channel
-                            && self.events[index].tick < self.events[index + 1].tick
-                        {
-                            result = true;
+                    let next = self.events[index + 1..]
+                        .iter()
+                        .find(|event| !matches!(event.data_kind, DataKind::ChangeTimbre(_)));
+                    if let Some(next) = next {
+                        if let DataKind::NoteOff(key_data_off) = next.data_kind {
+                            if key_data_on.key == key_data_off.key
+                                && self.events[index].channel == next.channel
+                                && self.events[index].sub_channel == next.sub_channel
+                                && self.events[index].tick < next.tick
+                            {
+                                result = true;
+                            }
                         }
                     }
-                    if result == false {
-                        println!(
-                            "Tick:{} Channel:{} Sub:{} Kind:{:?}",
-                            self.events[index].tick,
-                            self.events[index].channel,
-                            self.events[index].sub_channel,
-                            self.events[index].data_kind,
-                        );
-                        println!(
-                            "Tick:{} Channel:{} Sub:{} Kind:{:?}",
-                            self.events[index + 1].tick,
-                            self.events[index + 1].channel,
-                            self.events[index + 1].sub_channel,
-                            self.events[index + 1].data_kind,
-                        );
-                        return Err(format!("Sound integrity failed."));
+                    if !result {
+                        return Err(ConvertError::IntegrityFailed {
+                            track: self.track_index,
+                            tick: self.events[index].tick,
+                            channel: self.events[index].channel,
+                            sub_channel: self.events[index].sub_channel,
+                        });
                     }
                 }
             }
         }
 
-        println!("Valid Channel:{:?}", valid_channels);
-
         Ok(())
     }
 
     pub fn parse2(
         &mut self,
-        ticks_per_beat: u32,
-        tempo_events: &mut Vec<TempoEvent>,
-    ) -> Result<(), String> {
+        quantizer: &Quantizer,
+        tempo_events: &mut [TempoEvent],
+    ) -> Result<(), ConvertError> {
         for event in tempo_events.iter() {
             if event.enable {
                 for i in 0..self.channel_data_list.len() {
@@ -436,15 +405,23 @@ impl Track {
                 }
             }
         }
-        self.events.sort_by(|e1, e2| e1.tick.cmp(&e2.tick));
         self.events
-            .sort_by(|e1, e2| e1.sub_channel.cmp(&e2.sub_channel));
-        self.events.sort_by(|e1, e2| e1.channel.cmp(&e2.channel));
+            .sort_by_key(|e| !matches!(e.data_kind, DataKind::NoteOff(_)));
+        self.events.sort_by_key(|e| e.tick);
+        self.events.sort_by_key(|e| e.sub_channel);
+        self.events.sort_by_key(|e| e.channel);
 
         let mut channel = self.events[0].channel;
         let mut sub_channel = self.events[0].sub_channel;
         let mut pre_tick: u32 = 0;
-        for event in self.events.iter_mut() {
+        let mut note_on_tick: u32 = 0;
+        let shortest = quantizer.shortest();
+        // Program changes between notes move with the next NoteOn when it is snapped
+        // earlier, so they are not mistaken for changes in the middle of it.
+        let mut held = false;
+        let mut waiting: Vec<usize> = Vec::new();
+        for index in 0..self.events.len() {
+            let event = &mut self.events[index];
             /*
             println!(
                 "Tick:{} Channel:{} Sub:{} Kind:{:?}",
@@ -455,44 +432,74 @@ impl Track {
                 channel = event.channel;
                 sub_channel = event.sub_channel;
                 pre_tick = 0;
+                held = false;
+                waiting.clear();
             }
 
             match event.data_kind {
                 DataKind::NoteOn(_) => {
-                    let remainder = Self::divsion_tick(ticks_per_beat, event.tick - pre_tick);
-                    if remainder != 0 {
-                        println!(
-                            "Warning! Tick:{} Channel:{} Sub:{} Kind:{:?} Corrects NoteOn timing.",
-                            event.tick, event.channel, event.sub_channel, event.data_kind
-                        );
-                        event.tick -= remainder;
+                    let tick = quantizer.quantize(event.tick, pre_tick, pre_tick);
+                    if tick != event.tick {
+                        self.diagnostics.push(Self::event_warning(
+                            self.track_index,
+                            DiagnosticKind::NoteOnCorrected,
+                            event,
+                            Some(event.sub_channel),
+                        ));
+                        event.tick = tick;
+                    }
+                    note_on_tick = tick;
+                    pre_tick = tick;
+                    held = true;
+                    for waiting_index in waiting.drain(..) {
+                        let timbre_event = &mut self.events[waiting_index];
+                        timbre_event.tick = timbre_event.tick.min(tick);
                     }
-                    pre_tick = event.tick;
                 }
                 DataKind::NoteOff(_) => {
-                    let remainder = Self::divsion_tick(ticks_per_beat, event.tick - pre_tick);
-                    if remainder != 0 {
-                        println!(
-                            "Warning! Tick:{} Channel:{} Sub:{} Kind:{:?} Corrects NoteOff timing.",
-                            event.tick, event.channel, event.sub_channel, event.data_kind
-                        );
-                        event.tick -= remainder;
+                    // A note never collapses to nothing, it keeps at least one step.
+                    let mut lower = (note_on_tick + shortest).max(pre_tick);
+                    if !quantizer.aligned(lower, pre_tick) {
+                        lower = pre_tick + shortest;
+                    }
+                    let tick = quantizer.quantize(event.tick, lower, pre_tick);
+                    if quantizer.quantize(event.tick, pre_tick, pre_tick) <= note_on_tick {
+                        self.diagnostics.push(Self::event_warning(
+                            self.track_index,
+                            DiagnosticKind::NoteExtended,
+                            event,
+                            Some(event.sub_channel),
+                        ));
+                    } else if tick != event.tick {
+                        self.diagnostics.push(Self::event_warning(
+                            self.track_index,
+                            DiagnosticKind::NoteOffCorrected,
+                            event,
+                            Some(event.sub_channel),
+                        ));
+                    }
+                    event.tick = tick;
+                    pre_tick = tick;
+                    held = false;
+                }
+                DataKind::ChangeTimbre(_) => {
+                    if !held {
+                        waiting.push(index);
                     }
-                    pre_tick = event.tick;
                 }
-                DataKind::ChangeTimbre(_) => {}
                 DataKind::ChangeTempo(_) => {
-                    let remainder = Self::divsion_tick(ticks_per_beat, event.tick - pre_tick);
-                    if remainder != 0 {
+                    if !quantizer.aligned(event.tick, pre_tick) {
                         for tempo_event in tempo_events.iter_mut() {
                             if tempo_event.tick == event.tick {
                                 tempo_event.enable = false;
                             }
                         }
-                        println!(
-                            "Warning! Tick:{} Channel:{} Sub:{} Kind:{:?} Unsupport Change Tempo timing.",
-                            event.tick, event.channel, event.sub_channel, event.data_kind
-                        );
+                        self.diagnostics.push(Self::event_warning(
+                            self.track_index,
+                            DiagnosticKind::TempoChangeUnaligned,
+                            event,
+                            Some(event.sub_channel),
+                        ));
                     } else {
                         pre_tick = event.tick;
                     }
@@ -500,28 +507,33 @@ impl Track {
             }
         }
 
-        self.events.retain(|event| {
-            if let DataKind::ChangeTempo(_) = event.data_kind {
-                false
-            } else {
-                true
-            }
-        });
-
-        for index in 0..self.events.len() - 1 {
-            if self.events[index].channel == self.events[index + 1].channel
-                && self.events[index].sub_channel == self.events[index + 1].sub_channel
+        self.events
+            .retain(|event| !matches!(event.data_kind, DataKind::ChangeTempo(_)));
+
+        // Program changes are not quantised, parse3 sorts them in again.
+        let notes: Vec<&Event> = self
+            .events
+            .iter()
+            .filter(|event| !matches!(event.data_kind, DataKind::ChangeTimbre(_)))
+            .collect();
+        for pair in notes.windows(2) {
+            if pair[0].channel == pair[1].channel
+                && pair[0].sub_channel == pair[1].sub_channel
+                && pair[0].tick > pair[1].tick
             {
-                if self.events[index].tick > self.events[index + 1].tick {
-                    return Err(format!("Sound correction failed."));
-                }
+                return Err(ConvertError::CorrectionFailed {
+                    track: self.track_index,
+                    tick: pair[1].tick,
+                    channel: pair[1].channel,
+                    sub_channel: pair[1].sub_channel,
+                });
             }
         }
 
         Ok(())
     }
 
-    pub fn parse3(&mut self, tempo_events: &mut Vec<TempoEvent>) -> Result<(), String> {
+    pub fn parse3(&mut self, tempo_events: &mut [TempoEvent]) -> Result<(), ConvertError> {
         for event in tempo_events.iter() {
             if event.enable {
                 for i in 0..self.channel_data_list.len() {
@@ -540,200 +552,209 @@ impl Track {
             }
         }
 
-        self.events.sort_by(|e1, e2| e1.tick.cmp(&e2.tick));
         self.events
-            .sort_by(|e1, e2| e1.sub_channel.cmp(&e2.sub_channel));
-        self.events.sort_by(|e1, e2| e1.channel.cmp(&e2.channel));
+            .sort_by_key(|e| !matches!(e.data_kind, DataKind::NoteOff(_)));
+        self.events.sort_by_key(|e| e.tick);
+        self.events.sort_by_key(|e| e.sub_channel);
+        self.events.sort_by_key(|e| e.channel);
 
         for index in 0..self.events.len() - 1 {
             if self.events[index].channel == self.events[index + 1].channel
                 && self.events[index].sub_channel == self.events[index + 1].sub_channel
+                && self.events[index].tick > self.events[index + 1].tick
             {
-                if self.events[index].tick > self.events[index + 1].tick {
-                    return Err(format!("Music integrity failed."));
-                }
+                return Err(ConvertError::IntegrityFailed {
+                    track: self.track_index,
+                    tick: self.events[index + 1].tick,
+                    channel: self.events[index + 1].channel,
+                    sub_channel: self.events[index + 1].sub_channel,
+                });
             }
         }
         Ok(())
     }
 
-    fn calc_rest(mut ticks: u32, mml_status: &mut MmlStatus) {
-        let ticks_per_beat = mml_status.ticks_per_beat;
-        while ticks > 0 {
-            if ticks >= ticks_per_beat * 4 {
-                print!("r1");
-                ticks -= ticks_per_beat * 4;
-                mml_status.add_line_ticks(ticks_per_beat * 4);
-            } else if ticks >= ticks_per_beat * 2 {
-                print!("r2");
-                ticks -= ticks_per_beat * 2;
-                mml_status.add_line_ticks(ticks_per_beat * 2);
-            } else if ticks >= ticks_per_beat {
-                print!("r4");
-                ticks -= ticks_per_beat;
-                mml_status.add_line_ticks(ticks_per_beat);
-            } else if ticks % (ticks_per_beat / 3) == 0 {
-                print!("r12");
-                ticks -= ticks_per_beat / 3;
-                mml_status.add_line_ticks(ticks_per_beat / 3);
-            } else if ticks >= ticks_per_beat / 2 {
-                print!("r8");
-                ticks -= ticks_per_beat / 2;
-                mml_status.add_line_ticks(ticks_per_beat / 2);
-            } else if ticks % (ticks_per_beat / 6) == 0 {
-                print!("r24");
-                ticks -= ticks_per_beat / 6;
-                mml_status.add_line_ticks(ticks_per_beat / 6);
-            } else if ticks >= ticks_per_beat / 4 {
-                print!("r16");
-                ticks -= ticks_per_beat / 4;
-                mml_status.add_line_ticks(ticks_per_beat / 4);
-            } else if ticks % (ticks_per_beat / 12) == 0 {
-                print!("r48");
-                ticks -= ticks_per_beat / 12;
-                mml_status.add_line_ticks(ticks_per_beat / 12);
-            } else if ticks >= ticks_per_beat / 8 {
-                print!("r32");
-                ticks -= ticks_per_beat / 8;
-                mml_status.add_line_ticks(ticks_per_beat / 8);
-            } else if ticks % (ticks_per_beat / 24) == 0 {
-                print!("r96");
-                ticks -= ticks_per_beat / 24;
-                mml_status.add_line_ticks(ticks_per_beat / 24);
-            } else if ticks >= ticks_per_beat / 16 {
-                print!("r64");
-                ticks -= ticks_per_beat / 16;
-                mml_status.add_line_ticks(ticks_per_beat / 16);
-            } else if ticks % (ticks_per_beat / 48) == 0 {
-                print!("r192");
-                ticks -= ticks_per_beat / 48;
-                mml_status.add_line_ticks(ticks_per_beat / 24);
-            } else if ticks >= ticks_per_beat / 32 {
-                print!("r128");
-                ticks -= ticks_per_beat / 32;
-                mml_status.add_line_ticks(ticks_per_beat / 32);
-            }
+    fn calc_rest(ticks: u32, length_table: &LengthTable, mml_status: &mut MmlStatus) {
+        for (length, length_ticks) in length_table.split(ticks) {
+            mml_status.tokens.push(Token::Rest(length));
+            mml_status.add_line_ticks(length_ticks);
         }
     }
 
-    fn calc_note(mut ticks: u32, note: String, mml_status: &mut MmlStatus) {
-        let ticks_per_beat = mml_status.ticks_per_beat;
-        let line_ticks = ticks;
-
-        while ticks > 0 {
-            print!("{}", note);
-            if ticks >= ticks_per_beat * 4 {
-                print!("1");
-                ticks -= ticks_per_beat * 4;
-            } else if ticks >= ticks_per_beat * 2 {
-                print!("2");
-                ticks -= ticks_per_beat * 2;
-            } else if ticks >= ticks_per_beat {
-                print!("4");
-                ticks -= ticks_per_beat;
-            } else if ticks % (ticks_per_beat / 3) == 0 {
-                print!("12");
-                ticks -= ticks_per_beat / 3;
-            } else if ticks >= ticks_per_beat / 2 {
-                print!("8");
-                ticks -= ticks_per_beat / 2;
-            } else if ticks % (ticks_per_beat / 6) == 0 {
-                print!("24");
-                ticks -= ticks_per_beat / 6;
-            } else if ticks >= ticks_per_beat / 4 {
-                print!("16");
-                ticks -= ticks_per_beat / 4;
-            } else if ticks % (ticks_per_beat / 12) == 0 {
-                print!("48");
-                ticks -= ticks_per_beat / 12;
-            } else if ticks >= ticks_per_beat / 8 {
-                print!("32");
-                ticks -= ticks_per_beat / 8;
-            } else if ticks % (ticks_per_beat / 24) == 0 {
-                print!("96");
-                ticks -= ticks_per_beat / 24;
-            } else if ticks >= ticks_per_beat / 16 {
-                print!("64");
-                ticks -= ticks_per_beat / 16;
-            } else if ticks % (ticks_per_beat / 48) == 0 {
-                print!("192");
-                ticks -= ticks_per_beat / 48;
-            } else if ticks >= ticks_per_beat / 32 {
-                print!("128");
-                ticks -= ticks_per_beat / 32;
-            }
+    fn calc_note(key: u8, ticks: u32, length_table: &LengthTable, mml_status: &mut MmlStatus) {
+        let lengths = length_table
+            .split(ticks)
+            .into_iter()
+            .map(|(length, _)| length)
+            .collect();
+        mml_status.tokens.push(Token::Note { key, lengths });
+        mml_status.add_line_ticks(ticks);
+    }
 
-            if ticks > 0 {
-                print!("&");
+    // The tie goes right after the note, before any bar line the note completed.
+    fn tie_last_note(mml_status: &mut MmlStatus) {
+        if let Some(index) = mml_status
+            .tokens
+            .iter()
+            .rposition(|token| matches!(token, Token::Note { .. }))
+        {
+            mml_status.tokens.insert(index + 1, Token::Tie);
+        }
+    }
+
+    fn apply_deferred(
+        track_index: usize,
+        deferred: &mut Vec<&Event>,
+        tick: u32,
+        mml_status: &mut MmlStatus,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        for event in deferred.drain(..) {
+            if let DataKind::ChangeTimbre(timbre) = event.data_kind {
+                mml_status.tokens.push(Token::Timbre(timbre));
             }
+            diagnostics.push(
+                Self::event_warning(
+                    track_index,
+                    DiagnosticKind::TimbreChangeDuringNote,
+                    event,
+                    Some(event.sub_channel),
+                )
+                .with_message(format!(
+                    "Timbre change in the middle of a sound deferred to tick {}.",
+                    tick
+                )),
+            );
         }
-        mml_status.add_line_ticks(line_ticks);
     }
 
-    fn get_note(key: u8) -> String {
-        let note_names = [
-            "c", "c+", "d", "d+", "e", "f", "f+", "g", "g+", "a", "a+", "b",
-        ];
-        let index = (key % 12) as usize;
-        return note_names[index].to_string();
+    pub fn convert(
+        &mut self,
+        length_table: &LengthTable,
+        measure_map: &MeasureMap,
+        options: &ConvertOptions,
+        dialect: &dyn MmlDialect,
+    ) -> Vec<MmlPart> {
+        let mut parts: Vec<MmlPart> = Vec::new();
+        for events in self
+            .events
+            .chunk_by(|e1, e2| e1.channel == e2.channel && e1.sub_channel == e2.sub_channel)
+        {
+            let mut mml_status = MmlStatus::new(measure_map);
+            Self::write_part(
+                self.track_index,
+                events,
+                length_table,
+                dialect,
+                &mut mml_status,
+                &mut self.diagnostics,
+            );
+            if options.default_length {
+                optimize::default_length(&mut mml_status.tokens);
+            }
+            optimize::relative_octave(
+                &mut mml_status.tokens,
+                options.octave_mode,
+                options.octave_steps,
+                dialect.initial_octave(),
+            );
+            parts.push(MmlPart::new(
+                self.track_index,
+                events[0].channel,
+                events[0].sub_channel,
+                mml_status.tokens,
+            ));
+        }
+        parts
     }
 
-    pub fn convert(&self, ticks_per_beat: u32) {
-        let mut mml_status = MmlStatus::new(ticks_per_beat);
-        let mut channel = self.events[0].channel;
-        let mut sub_channel = self.events[0].sub_channel;
+    fn write_part(
+        track_index: usize,
+        events: &[Event],
+        length_table: &LengthTable,
+        dialect: &dyn MmlDialect,
+        mml_status: &mut MmlStatus,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
         let mut pre_tick = 0;
-        let mut volume = 75;
-        let mut octave = 4;
-        println!(
-            ";########## Track:{} Channel:{} Sub:{} ##########",
-            self.track_index, channel, sub_channel
-        );
-        for event in self.events.iter() {
-            if event.channel != channel || event.sub_channel != sub_channel {
-                print!("\n");
-                mml_status = MmlStatus::new(ticks_per_beat);
-                channel = event.channel;
-                sub_channel = event.sub_channel;
-                println!(
-                    ";########## Track:{} Channel:{} Sub:{} ##########",
-                    self.track_index, channel, sub_channel
-                );
-                pre_tick = 0;
-                volume = 75;
-                octave = 4;
-            }
+        let mut volume = dialect.initial_volume();
+        let mut octave = dialect.initial_octave();
+        let mut held: Option<u8> = None;
+        // Program changes while a note is held wait for the next tied segment or note.
+        let mut deferred: Vec<&Event> = Vec::new();
+        for event in events.iter() {
             match event.data_kind {
                 DataKind::NoteOn(key_data) => {
-                    Self::calc_rest(event.tick - pre_tick, &mut mml_status);
-                    if volume != key_data.vol {
-                        volume = key_data.vol;
-                        print!("v{}", volume);
+                    Self::calc_rest(event.tick - pre_tick, length_table, mml_status);
+                    if !dialect.key_range().contains(&key_data.key) {
+                        diagnostics.push(Self::event_warning(
+                            track_index,
+                            DiagnosticKind::KeyOutOfRange,
+                            event,
+                            Some(event.sub_channel),
+                        ));
                     }
-                    if octave != key_data.key / 12 {
-                        octave = key_data.key / 12;
-                        print!("o{}", octave);
+                    if volume != Some(dialect.volume(key_data.vol)) {
+                        volume = Some(dialect.volume(key_data.vol));
+                        mml_status
+                            .tokens
+                            .push(Token::Volume(dialect.volume(key_data.vol)));
                     }
+                    if octave != Some(dialect.octave(key_data.key)) {
+                        octave = Some(dialect.octave(key_data.key));
+                        mml_status
+                            .tokens
+                            .push(Token::Octave(dialect.octave(key_data.key)));
+                    }
+                    held = Some(key_data.key);
                     pre_tick = event.tick;
                 }
                 DataKind::NoteOff(key_data) => {
                     Self::calc_note(
+                        key_data.key,
                         event.tick - pre_tick,
-                        Self::get_note(key_data.key),
-                        &mut mml_status,
+                        length_table,
+                        mml_status,
+                    );
+                    held = None;
+                    Self::apply_deferred(
+                        track_index,
+                        &mut deferred,
+                        event.tick,
+                        mml_status,
+                        diagnostics,
                     );
                     pre_tick = event.tick;
                 }
                 DataKind::ChangeTempo(tempo) => {
-                    Self::calc_rest(event.tick - pre_tick, &mut mml_status);
-                    print!("t{}", tempo);
+                    match held {
+                        Some(key) if event.tick > pre_tick => {
+                            Self::calc_note(key, event.tick - pre_tick, length_table, mml_status);
+                            Self::tie_last_note(mml_status);
+                        }
+                        Some(_) => (),
+                        None => Self::calc_rest(event.tick - pre_tick, length_table, mml_status),
+                    }
+                    mml_status.tokens.push(Token::Tempo(tempo));
+                    if held.is_some() && event.tick > pre_tick {
+                        Self::apply_deferred(
+                            track_index,
+                            &mut deferred,
+                            event.tick,
+                            mml_status,
+                            diagnostics,
+                        );
+                    }
                     pre_tick = event.tick;
                 }
                 DataKind::ChangeTimbre(timbre) => {
-                    print!("@{}", timbre);
+                    if held.is_some() && event.tick > pre_tick {
+                        deferred.push(event);
+                    } else {
+                        mml_status.tokens.push(Token::Timbre(timbre));
+                    }
                 }
             }
         }
-        print!("\n");
     }
 }
@@ -0,0 +1,407 @@
+use super::event::Event;
+use super::track::DataKind;
+use crate::reverse::{MmlEventKind, MmlSong};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SourceNote {
+    pub track: usize,
+    pub channel: u8,
+    pub key: u8,
+    pub tick: u32,
+    pub ticks: u32,
+}
+
+impl SourceNote {
+    // Pairs every NoteOn with the first following NoteOff of the same channel and key.
+    pub fn collect(track: usize, events: &[Event]) -> Vec<SourceNote> {
+        let mut notes: Vec<SourceNote> = Vec::new();
+        let mut open: Vec<(u8, u8, u32)> = Vec::new();
+        for event in events.iter() {
+            match event.data_kind {
+                DataKind::NoteOn(key_data) => open.push((event.channel, key_data.key, event.tick)),
+                DataKind::NoteOff(key_data) => {
+                    if let Some(index) = open.iter().position(|(channel, key, _)| {
+                        *channel == event.channel && *key == key_data.key
+                    }) {
+                        let (channel, key, tick) = open.remove(index);
+                        notes.push(SourceNote {
+                            track,
+                            channel,
+                            key,
+                            tick,
+                            ticks: event.tick.saturating_sub(tick),
+                        });
+                    }
+                }
+                _ => (),
+            }
+        }
+        notes
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NoteMatch {
+    Matched {
+        start_delta: i64,
+        length_delta: i64,
+    },
+    PitchMismatch {
+        key: u8,
+        start_delta: i64,
+        length_delta: i64,
+    },
+    Dropped,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct VerifiedNote {
+    pub source: SourceNote,
+    pub result: NoteMatch,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Verification {
+    pub ticks_per_beat: u32,
+    pub notes: Vec<VerifiedNote>,
+    pub extra_notes: usize,
+}
+
+// Total of an alignment: same-key matches, then all matches, then the negated sum of
+// the start differences, so that larger is better.
+type Score = (u32, u32, i64);
+
+#[derive(Clone, Copy)]
+enum Step {
+    SkipSource,
+    SkipMml,
+    Match,
+}
+
+// Cells of the first `i` source notes against the first `first..` MML notes.
+struct Row {
+    first: usize,
+    cells: Vec<(Score, Step)>,
+}
+
+impl Row {
+    // Columns past the row cannot match any of its source notes and add nothing.
+    fn score(&self, column: usize) -> Score {
+        let last = self.first + self.cells.len() - 1;
+        self.cells[column.min(last) - self.first].0
+    }
+}
+
+impl Verification {
+    // The notes of every channel are aligned in order, so no two matches cross. A
+    // source note only matches an MML note starting within a beat of it.
+    pub fn new(ticks_per_beat: u32, mut source: Vec<SourceNote>, song: &MmlSong) -> Self {
+        let mut mml: Vec<(u8, u8, u32, u32)> = Vec::new();
+        for (index, track) in song.tracks.iter().enumerate() {
+            let channel = track.channel.unwrap_or(index as u8);
+            for event in track.events.iter() {
+                if let MmlEventKind::Note { key, ticks, .. } = event.kind {
+                    mml.push((channel, key, event.tick, ticks));
+                }
+            }
+        }
+        mml.sort_by_key(|(channel, key, tick, _)| (*channel, *tick, *key));
+
+        source.sort_by_key(|note| (note.tick, note.channel, note.key));
+        let mut notes: Vec<VerifiedNote> = source
+            .iter()
+            .map(|note| VerifiedNote {
+                source: *note,
+                result: NoteMatch::Dropped,
+            })
+            .collect();
+        let mut channels: Vec<u8> = source.iter().map(|note| note.channel).collect();
+        channels.sort();
+        channels.dedup();
+        let mut matched = 0;
+        for channel in channels {
+            let indices: Vec<usize> = (0..source.len())
+                .filter(|index| source[*index].channel == channel)
+                .collect();
+            let channel_source: Vec<SourceNote> =
+                indices.iter().map(|index| source[*index]).collect();
+            let channel_mml: Vec<(u8, u32, u32)> = mml
+                .iter()
+                .filter(|note| note.0 == channel)
+                .map(|(_, key, tick, ticks)| (*key, *tick, *ticks))
+                .collect();
+            for (source_index, mml_index) in
+                Self::align(ticks_per_beat, &channel_source, &channel_mml)
+            {
+                let note = &mut notes[indices[source_index]];
+                let (key, tick, ticks) = channel_mml[mml_index];
+                let start_delta = tick as i64 - note.source.tick as i64;
+                let length_delta = ticks as i64 - note.source.ticks as i64;
+                note.result = if key == note.source.key {
+                    NoteMatch::Matched {
+                        start_delta,
+                        length_delta,
+                    }
+                } else {
+                    NoteMatch::PitchMismatch {
+                        key,
+                        start_delta,
+                        length_delta,
+                    }
+                };
+                matched += 1;
+            }
+        }
+
+        Verification {
+            ticks_per_beat,
+            notes,
+            extra_notes: mml.len() - matched,
+        }
+    }
+
+    // Pairs of source and MML note indices of the best monotone alignment. Both lists
+    // are sorted by start, so the MML notes a source note can match form a window
+    // that only moves forward, and every row keeps the cells of its window alone.
+    fn align(
+        ticks_per_beat: u32,
+        source: &[SourceNote],
+        mml: &[(u8, u32, u32)],
+    ) -> Vec<(usize, usize)> {
+        let mut rows: Vec<Row> = vec![Row {
+            first: 0,
+            cells: vec![((0, 0, 0), Step::SkipSource)],
+        }];
+        for note in source.iter() {
+            let start = mml.partition_point(|(_, tick, _)| tick + ticks_per_beat < note.tick);
+            let end = mml.partition_point(|(_, tick, _)| *tick <= note.tick + ticks_per_beat);
+            let previous = &rows[rows.len() - 1];
+            let first = start.saturating_sub(1);
+            let mut cells: Vec<(Score, Step)> = Vec::with_capacity(end + 1 - first);
+            for column in first..=end {
+                let mut best = (previous.score(column), Step::SkipSource);
+                if let Some((score, _)) = cells.last() {
+                    if *score > best.0 {
+                        best = (*score, Step::SkipMml);
+                    }
+                }
+                if column > start {
+                    let (key, tick, _) = mml[column - 1];
+                    let (same_key, matches, delta) = previous.score(column - 1);
+                    let score = (
+                        same_key + (key == note.key) as u32,
+                        matches + 1,
+                        delta - tick.abs_diff(note.tick) as i64,
+                    );
+                    if score > best.0 {
+                        best = (score, Step::Match);
+                    }
+                }
+                cells.push(best);
+            }
+            rows.push(Row { first, cells });
+        }
+
+        let mut pairs: Vec<(usize, usize)> = Vec::new();
+        let mut column = mml.len();
+        for index in (1..rows.len()).rev() {
+            let row = &rows[index];
+            column = column.min(row.first + row.cells.len() - 1);
+            loop {
+                match row.cells[column - row.first].1 {
+                    Step::SkipSource => break,
+                    Step::SkipMml => column -= 1,
+                    Step::Match => {
+                        column -= 1;
+                        pairs.push((index - 1, column));
+                        break;
+                    }
+                }
+            }
+        }
+        pairs.reverse();
+        pairs
+    }
+
+    pub fn matched(&self) -> usize {
+        self.notes
+            .iter()
+            .filter(|note| matches!(note.result, NoteMatch::Matched { .. }))
+            .count()
+    }
+
+    pub fn pitch_mismatches(&self) -> usize {
+        self.notes
+            .iter()
+            .filter(|note| matches!(note.result, NoteMatch::PitchMismatch { .. }))
+            .count()
+    }
+
+    pub fn dropped(&self) -> usize {
+        self.notes
+            .iter()
+            .filter(|note| note.result == NoteMatch::Dropped)
+            .count()
+    }
+
+    pub fn max_start_delta(&self) -> u64 {
+        self.notes
+            .iter()
+            .filter_map(|note| match note.result {
+                NoteMatch::Matched { start_delta, .. } => Some(start_delta.unsigned_abs()),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Percentage over all source notes. A matched note scores 1, less its timing
+    // error (start plus half the length error) relative to a 16th note; mismatched
+    // and dropped notes score 0.
+    pub fn accuracy(&self) -> f64 {
+        if self.notes.is_empty() {
+            return 100.0;
+        }
+        let tolerance = (self.ticks_per_beat as f64 / 4.0).max(1.0);
+        let score: f64 = self
+            .notes
+            .iter()
+            .map(|note| match note.result {
+                NoteMatch::Matched {
+                    start_delta,
+                    length_delta,
+                } => {
+                    let error = start_delta.abs() as f64 + length_delta.abs() as f64 / 2.0;
+                    1.0 - error.min(tolerance) / tolerance
+                }
+                _ => 0.0,
+            })
+            .sum();
+        score * 100.0 / self.notes.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reverse::{MmlEvent, MmlTrack};
+    use crate::{ConvertOptions, Converter, OctaveDirection};
+
+    fn source_note(key: u8, tick: u32, ticks: u32) -> SourceNote {
+        SourceNote {
+            track: 0,
+            channel: 0,
+            key,
+            tick,
+            ticks,
+        }
+    }
+
+    fn song(notes: &[(u8, u32, u32)]) -> MmlSong {
+        let events = notes
+            .iter()
+            .map(|(key, tick, ticks)| {
+                MmlEvent::new(
+                    *tick,
+                    MmlEventKind::Note {
+                        key: *key,
+                        ticks: *ticks,
+                        velocity: 100,
+                    },
+                )
+            })
+            .collect();
+        MmlSong {
+            ticks_per_beat: 480,
+            tracks: vec![MmlTrack {
+                channel: Some(0),
+                events,
+            }],
+        }
+    }
+
+    fn start_deltas(verification: &Verification) -> Vec<Option<i64>> {
+        verification
+            .notes
+            .iter()
+            .map(|note| match note.result {
+                NoteMatch::Matched { start_delta, .. } => Some(start_delta),
+                NoteMatch::PitchMismatch { start_delta, .. } => Some(start_delta),
+                NoteMatch::Dropped => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_do_not_cross() {
+        // A quintuplet written as four 24ths and a 12th.
+        let source = (0..5)
+            .map(|index| source_note(60, index * 96, 96))
+            .collect();
+        let mml = song(&[
+            (60, 0, 80),
+            (60, 80, 80),
+            (60, 160, 80),
+            (60, 240, 80),
+            (60, 320, 160),
+        ]);
+        let verification = Verification::new(120, source, &mml);
+        assert_eq!(
+            start_deltas(&verification),
+            vec![Some(0), Some(-16), Some(-32), Some(-48), Some(-64)]
+        );
+    }
+
+    #[test]
+    fn same_key_preferred() {
+        let source = vec![source_note(60, 0, 480), source_note(64, 480, 480)];
+        let mml = song(&[(62, 0, 480), (60, 20, 460), (65, 480, 480)]);
+        let verification = Verification::new(480, source, &mml);
+        assert_eq!(
+            verification.notes[0].result,
+            NoteMatch::Matched {
+                start_delta: 20,
+                length_delta: -20
+            }
+        );
+        assert_eq!(
+            verification.notes[1].result,
+            NoteMatch::PitchMismatch {
+                key: 65,
+                start_delta: 0,
+                length_delta: 0
+            }
+        );
+        assert_eq!(verification.extra_notes, 1);
+    }
+
+    #[test]
+    fn unmatched_notes() {
+        let source = vec![source_note(60, 0, 480), source_note(62, 4800, 480)];
+        let mml = song(&[(60, 0, 480), (62, 1920, 480)]);
+        let verification = Verification::new(480, source, &mml);
+        assert_eq!(start_deltas(&verification), vec![Some(0), None]);
+        assert_eq!(verification.dropped(), 1);
+        assert_eq!(verification.extra_notes, 1);
+    }
+
+    #[test]
+    fn notes_across_bar_lines() {
+        // The converter writes an empty measure line for the second bar line c1&c1
+        // crosses, the part goes on after it.
+        let mml = crate::reverse::parse("t120 o5 c1&c1 d4 e2.&e1", 480, OctaveDirection::GreaterUp)
+            .unwrap();
+        let mut smf: Vec<u8> = Vec::new();
+        crate::reverse::write_smf(&mml, &mut smf).unwrap();
+        let options = ConvertOptions {
+            verify: true,
+            ..ConvertOptions::default()
+        };
+        let result = Converter::with_options(smf, options).convert().unwrap();
+        let verification = result.verification.unwrap();
+        assert_eq!(verification.notes.len(), 3);
+        assert_eq!(verification.matched(), 3);
+        assert_eq!(verification.extra_notes, 0);
+        assert_eq!(verification.accuracy(), 100.0);
+    }
+}
@@ -0,0 +1,9 @@
+mod converter;
+pub use converter::dialect;
+pub mod reverse;
+pub use converter::{
+    ConversionResult, ConvertError, ConvertOptions, Converter, Diagnostic, DiagnosticKind, Length,
+    MmlDialect, MmlPart, NoteMatch, OctaveDirection, OctaveMode, Rounding, Severity, SourceNote,
+    Token, TrackInfo, Verification, VerifiedNote,
+};
+pub use reverse::{mml2midi, MmlError};
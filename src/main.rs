@@ -1,41 +1,172 @@
 use std::env;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, BufWriter, Read};
 
-mod converter;
-use converter::Converter;
+use midi2mml::{dialect, ConversionResult, Converter, MmlDialect, NoteMatch, Verification};
 
-fn read_file(buffer: &mut Vec<u8>) -> std::io::Result<()> {
-    let args: Vec<String> = env::args().collect();
+mod cli;
+use cli::{Args, Command, DiagnosticsMode};
 
-    if args.len() != 2 {
-        let error_message: String = format!("Usage: {} <midi_filepath>", args[0]);
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            error_message,
-        ));
+fn read_file(file_path: &str, buffer: &mut Vec<u8>) -> io::Result<()> {
+    if file_path == "-" {
+        io::stdin().read_to_end(buffer)?;
+    } else {
+        let mut file: File = File::open(file_path)?;
+        file.read_to_end(buffer)?;
     }
 
-    let file_path: &String = &args[1];
+    Ok(())
+}
 
-    let mut file: File = File::open(file_path)?;
-    file.read_to_end(buffer)?;
+fn write_result(result: &ConversionResult, output_path: &Option<String>) -> io::Result<()> {
+    match output_path {
+        Some(path) if path != "-" => {
+            let mut writer = BufWriter::new(File::create(path)?);
+            result.write_to(&mut writer)
+        }
+        _ => result.write_to(&mut io::stdout().lock()),
+    }
+}
 
-    Ok(())
+fn report_summary(result: &ConversionResult, args: &Args) {
+    eprintln!("dialect {}", args.dialect);
+    eprintln!("ticks_per_beat {}", result.ticks_per_beat);
+    eprintln!("track num: {}", result.track_count());
+    for track in result.tracks.iter() {
+        eprintln!(
+            "track {} has {} events Channel:{:?}",
+            track.index, track.event_count, track.channels
+        );
+    }
+    for part in result.parts.iter() {
+        eprintln!(
+            "Track:{} Channel:{} Sub:{} {} bytes",
+            part.track,
+            part.channel,
+            part.sub_channel,
+            part.mml.len()
+        );
+    }
+}
+
+fn report_diagnostics(result: &ConversionResult, mode: &DiagnosticsMode) {
+    match mode {
+        DiagnosticsMode::Stderr => {
+            for diagnostic in result.diagnostics.iter() {
+                eprintln!("{}", diagnostic);
+            }
+        }
+        DiagnosticsMode::None => (),
+        DiagnosticsMode::Json => {
+            let items: Vec<String> = result
+                .diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.to_json())
+                .collect();
+            eprintln!("[{}]", items.join(","));
+        }
+    }
+}
+
+fn report_verification(verification: &Verification) {
+    for note in verification.notes.iter() {
+        let source = &note.source;
+        let prefix = format!(
+            "Verify: Track:{} Tick:{} Channel:{} Key:{}",
+            source.track, source.tick, source.channel, source.key
+        );
+        match note.result {
+            NoteMatch::Matched {
+                start_delta: 0,
+                length_delta: 0,
+            } => (),
+            NoteMatch::Matched {
+                start_delta,
+                length_delta,
+            } => eprintln!(
+                "{} Start:{:+} Length:{:+}",
+                prefix, start_delta, length_delta
+            ),
+            NoteMatch::PitchMismatch {
+                key,
+                start_delta,
+                length_delta,
+            } => eprintln!(
+                "{} Pitch mismatch, MML Key:{} Start:{:+} Length:{:+}",
+                prefix, key, start_delta, length_delta
+            ),
+            NoteMatch::Dropped => eprintln!("{} Dropped", prefix),
+        }
+    }
+    eprintln!(
+        "Verify: {} notes, {} matched, {} pitch mismatches, {} dropped, {} extra, max start delta {} ticks, accuracy {:.1}%",
+        verification.notes.len(),
+        verification.matched(),
+        verification.pitch_mismatches(),
+        verification.dropped(),
+        verification.extra_notes,
+        verification.max_start_delta(),
+        verification.accuracy()
+    );
 }
 
 fn main() {
     let mut data: Vec<u8> = Vec::new();
     let mut converter: Converter;
 
-    if let Err(err) = read_file(&mut data) {
+    let args: Vec<String> = env::args().collect();
+    let program = args.first().map(|arg| arg.as_str()).unwrap_or("midi2mml");
+    let args = match cli::parse_args(&args) {
+        Ok(Command::Convert(args)) => args,
+        Ok(Command::Help) => {
+            println!("{}", cli::help(program));
+            return;
+        }
+        Ok(Command::Version) => {
+            println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(termination::EXIT_FAILURE);
+        }
+    };
+
+    if let Err(err) = read_file(&args.file_path, &mut data) {
         eprintln!("{}", err);
         std::process::exit(termination::EXIT_FAILURE);
     }
 
-    converter = Converter::new(data);
-    if let Err(msg) = converter.convert() {
-        eprintln!("{}", msg);
-        std::process::exit(termination::EXIT_FAILURE);
+    let dialect: Option<Box<dyn MmlDialect>> = match (args.dialect.as_str(), &args.char_limits) {
+        ("mabinogi", Some(limits)) => Some(Box::new(dialect::Mabinogi::new(limits.clone()))),
+        ("pmd", _) => Some(Box::new(dialect::Pmd::new(args.pmd_policy))),
+        (name, _) => dialect::by_name(name),
+    };
+    let dialect = match dialect {
+        Some(dialect) => dialect,
+        None => {
+            eprintln!("Unknown dialect: {}", args.dialect);
+            std::process::exit(termination::EXIT_FAILURE);
+        }
+    };
+    converter = Converter::with_dialect(data, args.options.clone(), dialect);
+    match converter.convert() {
+        Ok(result) => {
+            if args.verbose {
+                report_summary(&result, &args);
+            }
+            report_diagnostics(&result, &args.diagnostics);
+            if let Some(verification) = &result.verification {
+                report_verification(verification);
+            }
+            if let Err(err) = write_result(&result, &args.output_path) {
+                eprintln!("{}", err);
+                std::process::exit(termination::EXIT_FAILURE);
+            }
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(termination::EXIT_FAILURE);
+        }
     }
 }
@@ -0,0 +1,92 @@
+use std::fmt;
+use std::io;
+
+use crate::OctaveDirection;
+
+mod parser;
+pub use parser::parse;
+mod writer;
+pub use writer::write_smf;
+
+pub const DEFAULT_TICKS_PER_BEAT: u16 = 480;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MmlEventKind {
+    Note { key: u8, ticks: u32, velocity: u8 },
+    Tempo(u32),
+    Program(u8),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MmlEvent {
+    pub tick: u32,
+    pub kind: MmlEventKind,
+}
+
+impl MmlEvent {
+    pub fn new(tick: u32, kind: MmlEventKind) -> Self {
+        MmlEvent { tick, kind }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MmlTrack {
+    pub channel: Option<u8>,
+    pub events: Vec<MmlEvent>,
+}
+
+#[derive(Clone, Debug)]
+pub struct MmlSong {
+    pub ticks_per_beat: u16,
+    pub tracks: Vec<MmlTrack>,
+}
+
+#[derive(Debug)]
+pub enum MmlError {
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for MmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MmlError::Syntax {
+                line,
+                column,
+                message,
+            } => write!(f, "Line:{} Column:{} {}", line, column, message),
+            MmlError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for MmlError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MmlError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MmlError {
+    fn from(err: io::Error) -> Self {
+        MmlError::Io(err)
+    }
+}
+
+// Parses MML written for the RP2040 MML Player and returns it as a Standard MIDI File.
+pub fn mml2midi(
+    text: &str,
+    ticks_per_beat: u16,
+    octave_direction: OctaveDirection,
+) -> Result<Vec<u8>, MmlError> {
+    let song = parse(text, ticks_per_beat, octave_direction)?;
+    let mut data: Vec<u8> = Vec::new();
+    write_smf(&song, &mut data)?;
+    Ok(data)
+}
@@ -0,0 +1,426 @@
+use super::{MmlError, MmlEvent, MmlEventKind, MmlSong, MmlTrack};
+use crate::OctaveDirection;
+
+const DEFAULT_OCTAVE: i32 = 4;
+const DEFAULT_VOLUME: u32 = 75;
+const DEFAULT_LENGTH: u32 = 4;
+const MAX_DOTS: u8 = 8;
+const MAX_VOLUME: u32 = 100;
+// Slower tempos do not fit the 24-bit microseconds per beat of a MIDI file.
+const MIN_TEMPO: u32 = 4;
+
+struct PartState {
+    track: MmlTrack,
+    tick: u32,
+    octave: i32,
+    volume: u32,
+    length: (u32, u8),
+    tie: bool,
+}
+
+impl PartState {
+    fn new(channel: Option<u8>) -> Self {
+        PartState {
+            track: MmlTrack {
+                channel,
+                events: Vec::new(),
+            },
+            tick: 0,
+            octave: DEFAULT_OCTAVE,
+            volume: DEFAULT_VOLUME,
+            length: (DEFAULT_LENGTH, 0),
+            tie: false,
+        }
+    }
+
+    fn velocity(&self) -> u8 {
+        ((self.volume.min(MAX_VOLUME) * 127 + MAX_VOLUME / 2) / MAX_VOLUME) as u8
+    }
+
+    // Extends the last note when it has the same key and ends at the current tick.
+    fn extend(&mut self, key: Option<u8>, ticks: u32) -> bool {
+        let tick = self.tick;
+        let last = self
+            .track
+            .events
+            .iter_mut()
+            .rev()
+            .find_map(|event| match &mut event.kind {
+                MmlEventKind::Note {
+                    key: last_key,
+                    ticks: last_ticks,
+                    ..
+                } if event.tick + *last_ticks == tick && key.is_none_or(|key| key == *last_key) => {
+                    Some(last_ticks)
+                }
+                _ => None,
+            });
+        match last {
+            Some(last_ticks) => {
+                *last_ticks += ticks;
+                self.tick += ticks;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+struct Cursor<'a> {
+    chars: Vec<char>,
+    index: usize,
+    line: usize,
+    text: &'a str,
+}
+
+impl Cursor<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn error(&self, message: String) -> MmlError {
+        MmlError::Syntax {
+            line: self.line,
+            column: self.index + 1,
+            message: format!("{} in \"{}\"", message, self.text.trim()),
+        }
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let start = self.index;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.index += 1;
+        }
+        self.chars[start..self.index]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()
+    }
+
+    fn required_number(&mut self, command: char) -> Result<u32, MmlError> {
+        self.number()
+            .ok_or_else(|| self.error(format!("Missing value for {}", command)))
+    }
+
+    // Adds the dots that follow to `dots`.
+    fn dots(&mut self, mut dots: u8) -> Result<u8, MmlError> {
+        while self.peek() == Some('.') {
+            if dots == MAX_DOTS {
+                return Err(self.error(format!("More than {} dots", MAX_DOTS)));
+            }
+            self.index += 1;
+            dots += 1;
+        }
+        Ok(dots)
+    }
+
+    // Explicit length with dots, or the default length with any dots added to it.
+    fn length(&mut self, default: (u32, u8)) -> Result<(u32, u8), MmlError> {
+        let length = match self.number() {
+            Some(0) => return Err(self.error("Length 0".to_string())),
+            Some(denominator) => (denominator, 0),
+            None => default,
+        };
+        Ok((length.0, self.dots(length.1)?))
+    }
+}
+
+fn length_ticks(ticks_per_beat: u16, (denominator, dots): (u32, u8)) -> u32 {
+    let whole = ticks_per_beat as u32 * 4;
+    let mut ticks = (whole + denominator / 2) / denominator;
+    let mut dot_ticks = ticks;
+    for _ in 0..dots {
+        dot_ticks /= 2;
+        ticks += dot_ticks;
+    }
+    ticks
+}
+
+fn header_channel(comment: &str) -> Option<u8> {
+    let start = comment.find("Channel:")? + "Channel:".len();
+    comment[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<String>()
+        .parse()
+        .ok()
+}
+
+// A part ends at a `;#` header comment such as the ones the converter writes before
+// every part.
+pub fn parse(
+    text: &str,
+    ticks_per_beat: u16,
+    octave_direction: OctaveDirection,
+) -> Result<MmlSong, MmlError> {
+    let mut song = MmlSong {
+        ticks_per_beat,
+        tracks: Vec::new(),
+    };
+    let mut part = PartState::new(None);
+    let mut part_used = false;
+    for (line_index, line) in text.lines().enumerate() {
+        let (code, comment) = match line.find(';') {
+            Some(index) => (&line[..index], Some(&line[index + 1..])),
+            None => (line, None),
+        };
+        let header = code.trim().is_empty() && comment.is_some_and(|c| c.starts_with('#'));
+        if header {
+            if part_used {
+                song.tracks.push(part.track);
+            }
+            part = PartState::new(comment.and_then(header_channel));
+            part_used = false;
+            continue;
+        }
+        // Notes longer than a measure leave empty measure lines inside a part.
+        if code.trim().is_empty() {
+            continue;
+        }
+
+        let mut cursor = Cursor {
+            chars: code.chars().collect(),
+            index: 0,
+            line: line_index + 1,
+            text: line,
+        };
+        while let Some(c) = cursor.peek() {
+            cursor.index += 1;
+            let command = c.to_ascii_lowercase();
+            match command {
+                'c' | 'd' | 'e' | 'f' | 'g' | 'a' | 'b' => {
+                    let mut semitone: i32 = match command {
+                        'c' => 0,
+                        'd' => 2,
+                        'e' => 4,
+                        'f' => 5,
+                        'g' => 7,
+                        'a' => 9,
+                        _ => 11,
+                    };
+                    loop {
+                        match cursor.peek() {
+                            Some('+') | Some('#') => semitone += 1,
+                            Some('-') => semitone -= 1,
+                            _ => break,
+                        }
+                        cursor.index += 1;
+                    }
+                    let ticks = length_ticks(ticks_per_beat, cursor.length(part.length)?);
+                    let key = part.octave * 12 + semitone;
+                    if !(0..=127).contains(&key) {
+                        return Err(cursor.error(format!("Key {} out of range", key)));
+                    }
+                    let key = key as u8;
+                    let tied = part.tie && part.extend(Some(key), ticks);
+                    if !tied {
+                        if part.volume > 0 {
+                            part.track.events.push(MmlEvent::new(
+                                part.tick,
+                                MmlEventKind::Note {
+                                    key,
+                                    ticks,
+                                    velocity: part.velocity(),
+                                },
+                            ));
+                        }
+                        part.tick += ticks;
+                    }
+                    part.tie = false;
+                }
+                'r' => {
+                    part.tick += length_ticks(ticks_per_beat, cursor.length(part.length)?);
+                    part.tie = false;
+                }
+                '&' => {
+                    // `c4&8` extends the note without repeating its name.
+                    if cursor.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        let ticks = length_ticks(ticks_per_beat, cursor.length(part.length)?);
+                        if !part.extend(None, ticks) {
+                            part.tick += ticks;
+                        }
+                    } else {
+                        part.tie = true;
+                    }
+                }
+                'o' => part.octave = cursor.required_number(c)? as i32,
+                '>' | '<' => {
+                    let up = (command == '>') == (octave_direction == OctaveDirection::GreaterUp);
+                    part.octave += if up { 1 } else { -1 };
+                }
+                'l' => {
+                    let denominator = cursor.required_number(c)?;
+                    if denominator == 0 {
+                        return Err(cursor.error("Length 0".to_string()));
+                    }
+                    part.length = (denominator, cursor.dots(0)?);
+                }
+                'v' => part.volume = cursor.required_number(c)?,
+                't' => {
+                    let tempo = cursor.required_number(c)?;
+                    if tempo < MIN_TEMPO {
+                        return Err(cursor.error(format!("Tempo {} below {}", tempo, MIN_TEMPO)));
+                    }
+                    part.track
+                        .events
+                        .push(MmlEvent::new(part.tick, MmlEventKind::Tempo(tempo)));
+                }
+                '@' => {
+                    let program = cursor.required_number(c)?;
+                    part.track.events.push(MmlEvent::new(
+                        part.tick,
+                        MmlEventKind::Program(program.min(127) as u8),
+                    ));
+                }
+                c if c.is_whitespace() => continue,
+                _ => {
+                    cursor.index -= 1;
+                    return Err(cursor.error(format!("Unknown command {}", c)));
+                }
+            }
+            part_used = true;
+        }
+    }
+    if part_used {
+        song.tracks.push(part.track);
+    }
+    Ok(song)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICKS_PER_BEAT: u16 = 480;
+
+    fn parse_part(text: &str, octave_direction: OctaveDirection) -> Vec<MmlEvent> {
+        let song = parse(text, TICKS_PER_BEAT, octave_direction).unwrap();
+        assert_eq!(song.tracks.len(), 1);
+        song.tracks[0].events.clone()
+    }
+
+    // (tick, key, ticks) of every note.
+    fn notes(text: &str) -> Vec<(u32, u8, u32)> {
+        parse_part(text, OctaveDirection::GreaterUp)
+            .iter()
+            .filter_map(|event| match event.kind {
+                MmlEventKind::Note { key, ticks, .. } => Some((event.tick, key, ticks)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn error(text: &str) -> String {
+        match parse(text, TICKS_PER_BEAT, OctaveDirection::GreaterUp) {
+            Err(MmlError::Syntax { message, .. }) => message,
+            result => panic!("expected a syntax error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn relative_octave() {
+        assert_eq!(
+            notes("o4c>c<<c"),
+            vec![(0, 48, 480), (480, 60, 480), (960, 36, 480)]
+        );
+        let keys: Vec<u8> = parse_part("o4c>c", OctaveDirection::LessUp)
+            .iter()
+            .filter_map(|event| match event.kind {
+                MmlEventKind::Note { key, .. } => Some(key),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(keys, vec![48, 36]);
+    }
+
+    #[test]
+    fn ties() {
+        assert_eq!(notes("c4&c8"), vec![(0, 48, 720)]);
+        assert_eq!(notes("c4&8&16"), vec![(0, 48, 840)]);
+        assert_eq!(notes("c4&d4"), vec![(0, 48, 480), (480, 50, 480)]);
+        assert_eq!(notes("r4&8c"), vec![(720, 48, 480)]);
+    }
+
+    #[test]
+    fn dots() {
+        assert_eq!(notes("c4."), vec![(0, 48, 720)]);
+        assert_eq!(notes("c4.."), vec![(0, 48, 840)]);
+        assert_eq!(notes("l4....c...."), vec![(0, 48, 956)]);
+        assert_eq!(notes("c2.r8c"), vec![(0, 48, 1440), (1680, 48, 480)]);
+    }
+
+    #[test]
+    fn default_length() {
+        assert_eq!(
+            notes("l8cc4c"),
+            vec![(0, 48, 240), (240, 48, 480), (720, 48, 240)]
+        );
+        assert_eq!(notes("l8.c"), vec![(0, 48, 360)]);
+        assert_eq!(notes("l8c."), vec![(0, 48, 360)]);
+        assert_eq!(notes("l3c"), vec![(0, 48, 640)]);
+    }
+
+    #[test]
+    fn tempo() {
+        let events = parse_part("t150c t90c", OctaveDirection::GreaterUp);
+        let tempos: Vec<(u32, u32)> = events
+            .iter()
+            .filter_map(|event| match event.kind {
+                MmlEventKind::Tempo(tempo) => Some((event.tick, tempo)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(tempos, vec![(0, 150), (480, 90)]);
+        assert!(error("t3c").starts_with("Tempo 3"));
+        assert!(error("t0c").starts_with("Tempo 0"));
+    }
+
+    #[test]
+    fn parts() {
+        let song = parse(
+            ";########## Track:1 Channel:2 Sub:0 ##########\nc\n\nd\n;# Channel:3\ne",
+            TICKS_PER_BEAT,
+            OctaveDirection::GreaterUp,
+        )
+        .unwrap();
+        let channels: Vec<Option<u8>> = song.tracks.iter().map(|track| track.channel).collect();
+        assert_eq!(channels, vec![Some(2), Some(3)]);
+        assert_eq!(song.tracks[0].events.len(), 2);
+    }
+
+    #[test]
+    fn round_trip() {
+        // c1&c1 crosses two bar lines, so the converter leaves an empty measure line.
+        let text = "t120 o5 c1&c1 d4";
+        let mut smf: Vec<u8> = Vec::new();
+        crate::reverse::write_smf(
+            &parse(text, TICKS_PER_BEAT, OctaveDirection::GreaterUp).unwrap(),
+            &mut smf,
+        )
+        .unwrap();
+        let result = crate::Converter::new(smf).convert().unwrap();
+        assert!(result.document.contains("\n\n"));
+
+        let original = parse_part(text, OctaveDirection::GreaterUp);
+        let song = parse(&result.document, TICKS_PER_BEAT, OctaveDirection::GreaterUp).unwrap();
+        assert_eq!(song.tracks.len(), 1);
+        assert_eq!(song.tracks[0].events, original);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(error("x").starts_with("Unknown command x"));
+        assert!(error("c0").starts_with("Length 0"));
+        assert!(error("l0c").starts_with("Length 0"));
+        assert!(error("oc").starts_with("Missing value for o"));
+        assert!(error("o11c").starts_with("Key 132 out of range"));
+        assert!(error(&format!("c4{}", ".".repeat(300))).starts_with("More than 8 dots"));
+        assert!(error(&format!("l4{}c", ".".repeat(200))).starts_with("More than 8 dots"));
+        assert!(error("l4.....c....").starts_with("More than 8 dots"));
+        match parse("c\ncx", TICKS_PER_BEAT, OctaveDirection::GreaterUp) {
+            Err(MmlError::Syntax { line, column, .. }) => assert_eq!((line, column), (2, 2)),
+            result => panic!("expected a syntax error, got {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::io;
+
+use midly::num::{u15, u24, u28, u4, u7};
+use midly::{Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
+
+use super::{MmlEventKind, MmlSong};
+
+const DRUM_CHANNEL: u8 = 9;
+
+fn track_events<'a>(mut events: Vec<(u32, u8, TrackEventKind<'a>)>) -> Vec<TrackEvent<'a>> {
+    // Note offs (order 0) come before anything else starting on the same tick.
+    events.sort_by_key(|(tick, order, _)| (*tick, *order));
+    let mut track: Vec<TrackEvent> = Vec::new();
+    let mut pre_tick = 0;
+    for (tick, _, kind) in events {
+        track.push(TrackEvent {
+            delta: u28::new(tick - pre_tick),
+            kind,
+        });
+        pre_tick = tick;
+    }
+    track.push(TrackEvent {
+        delta: u28::new(0),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+    track
+}
+
+// Writes a format 1 file: a conductor track holding every tempo change, then one
+// track per part. Parts without a channel take the next free one, skipping drums.
+pub fn write_smf<W: io::Write>(song: &MmlSong, out: &mut W) -> io::Result<()> {
+    let mut tempos: Vec<(u32, u32)> = Vec::new();
+    for track in song.tracks.iter() {
+        for event in track.events.iter() {
+            if let MmlEventKind::Tempo(tempo) = event.kind {
+                if !tempos.contains(&(event.tick, tempo)) {
+                    tempos.push((event.tick, tempo));
+                }
+            }
+        }
+    }
+    tempos.sort_by_key(|(tick, _)| *tick);
+    tempos.dedup_by_key(|(tick, _)| *tick);
+
+    let mut tracks: Vec<Vec<TrackEvent>> = Vec::new();
+    tracks.push(track_events(
+        tempos
+            .iter()
+            .map(|(tick, tempo)| {
+                (
+                    *tick,
+                    1,
+                    TrackEventKind::Meta(MetaMessage::Tempo(u24::new(60_000_000 / *tempo))),
+                )
+            })
+            .collect(),
+    ));
+
+    let mut next_channel: u8 = 0;
+    for track in song.tracks.iter() {
+        let channel = match track.channel {
+            Some(channel) => channel.min(15),
+            None => {
+                if next_channel == DRUM_CHANNEL {
+                    next_channel += 1;
+                }
+                let channel = next_channel % 16;
+                next_channel += 1;
+                channel
+            }
+        };
+        let midi = |message: MidiMessage| TrackEventKind::Midi {
+            channel: u4::new(channel),
+            message,
+        };
+        let mut events: Vec<(u32, u8, TrackEventKind)> = Vec::new();
+        for event in track.events.iter() {
+            match event.kind {
+                MmlEventKind::Note {
+                    key,
+                    ticks,
+                    velocity,
+                } => {
+                    events.push((
+                        event.tick,
+                        2,
+                        midi(MidiMessage::NoteOn {
+                            key: u7::new(key),
+                            vel: u7::new(velocity.max(1)),
+                        }),
+                    ));
+                    events.push((
+                        event.tick + ticks,
+                        0,
+                        midi(MidiMessage::NoteOff {
+                            key: u7::new(key),
+                            vel: u7::new(0),
+                        }),
+                    ));
+                }
+                MmlEventKind::Program(program) => events.push((
+                    event.tick,
+                    1,
+                    midi(MidiMessage::ProgramChange {
+                        program: u7::new(program),
+                    }),
+                )),
+                MmlEventKind::Tempo(_) => (),
+            }
+        }
+        tracks.push(track_events(events));
+    }
+
+    let smf = Smf {
+        header: Header::new(
+            Format::Parallel,
+            Timing::Metrical(u15::new(song.ticks_per_beat)),
+        ),
+        tracks,
+    };
+    smf.write_std(out)
+}